@@ -1,10 +1,11 @@
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
-use rand::{Rng, SeedableRng};
-use rand_chacha::ChaCha20Rng;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use std::collections::HashSet;
 
 use fuzzy_psi::okvs::lagrange::{LagrangePolynomialOKVS, Point as LagrangePoint};
+use fuzzy_psi::okvs::near_optimal::band::Band;
 use fuzzy_psi::okvs::near_optimal::okvs::{Okvs, OkvsKey, OkvsValue, RbOkvs};
+use fuzzy_psi::okvs::near_optimal::values::{ByteValues, Values};
+use rand::{Rng, RngCore};
 
 fn lagrange_points(n: usize) -> HashSet<LagrangePoint> {
     (1..=n as u64).map(|i| LagrangePoint::new(i, i)).collect()
@@ -71,5 +72,170 @@ fn bench_okvs(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_okvs);
+fn random_pairs<const N: usize>(n: usize) -> Vec<(OkvsKey, OkvsValue<N>)> {
+    let mut rng = rand::rng();
+    (1..=n as u64)
+        .map(|i| {
+            let mut value = [0u8; N];
+            rng.fill_bytes(&mut value);
+            (OkvsKey(i.to_le_bytes()), OkvsValue(value))
+        })
+        .collect()
+}
+
+/// Decoding costs one XOR of a value per set bit of the band, so it is dominated by
+/// the value size for large values.
+fn bench_decode_value_size<const N: usize>(c: &mut Criterion, n: usize) {
+    let mut group = c.benchmark_group("RbOkvs decode value size");
+    group.sample_size(10);
+
+    let data = random_pairs::<N>(n);
+    let rb = RbOkvs::new(n);
+    let keys: Vec<_> = data.iter().map(|(k, _)| k.clone()).collect();
+
+    let encoding = rb.encode(data.clone()).unwrap();
+    group.bench_function(format!("OkvsValue<{}> n={}", N, n), |b| {
+        b.iter(|| {
+            for k in &keys {
+                let _ = rb.decode(&encoding, k);
+            }
+        });
+    });
+
+    let bytes: Vec<_> = data.iter().map(|(k, v)| (k.clone(), &v.0[..])).collect();
+    let encoding = rb.encode_bytes(bytes, N).unwrap();
+    group.bench_function(format!("Bytes({}) n={}", N, n), |b| {
+        b.iter(|| {
+            for k in &keys {
+                let _ = rb.decode(&encoding, k);
+            }
+        });
+    });
+    group.finish();
+}
+
+/// The inner product of the first version of RB-OKVS, kept as the baseline for
+/// [`bench_inner_product`]: it tests every bit below the bit length of the band
+/// against a table of masks and XORs the values byte by byte. The band is given as
+/// the four little-endian words of the `U256` it was stored in.
+mod baseline {
+    use fuzzy_psi::okvs::near_optimal::okvs::OkvsValue;
+
+    const MASK: [u64; 64] = {
+        let mut mask = [0; 64];
+        let mut i = 0;
+        while i < 64 {
+            mask[i] = 1 << i;
+            i += 1;
+        }
+        mask
+    };
+
+    fn in_place_xor<const N: usize>(value: &mut OkvsValue<N>, other: &OkvsValue<N>) {
+        for i in 0..value.0.len() {
+            value.0[i] ^= other.0[i];
+        }
+    }
+
+    pub fn inner_product<const N: usize>(m: &[u64; 4], x: &[OkvsValue<N>]) -> OkvsValue<N> {
+        let mut result = OkvsValue([0u8; N]);
+        // the bit length, as `U256::bits`
+        let bits = (0..4)
+            .rev()
+            .find(|&w| m[w] != 0)
+            .map_or(0, |w| 64 * (w + 1) - m[w].leading_zeros() as usize);
+
+        if bits <= 64 {
+            for i in 0..bits {
+                if m[0] & MASK[i] != 0 {
+                    in_place_xor(&mut result, &x[i]);
+                }
+            }
+            return result;
+        }
+
+        for i in 0..64 {
+            if m[0] & MASK[i] != 0 {
+                in_place_xor(&mut result, &x[i]);
+            }
+        }
+
+        let x64 = &x[64..];
+
+        if bits <= 128 {
+            for i in 0..bits - 64 {
+                if m[1] & MASK[i] != 0 {
+                    in_place_xor(&mut result, &x64[i]);
+                }
+            }
+            return result;
+        }
+
+        for i in 0..64 {
+            if m[1] & MASK[i] != 0 {
+                in_place_xor(&mut result, &x64[i]);
+            }
+        }
+
+        let x128 = &x[128..];
+
+        if bits <= 192 {
+            for i in 0..bits - 128 {
+                if m[2] & MASK[i] != 0 {
+                    in_place_xor(&mut result, &x128[i]);
+                }
+            }
+            return result;
+        }
+
+        for i in 0..64 {
+            if m[2] & MASK[i] != 0 {
+                in_place_xor(&mut result, &x128[i]);
+            }
+        }
+
+        let x192 = &x[192..];
+
+        for i in 0..bits - 192 {
+            if m[3] & MASK[i] != 0 {
+                in_place_xor(&mut result, &x192[i]);
+            }
+        }
+        result
+    }
+}
+
+/// The inner product of a band with the values alone, i.e., decoding without hashing
+/// the key, next to the baseline of the first version.
+fn bench_inner_product<const N: usize>(c: &mut Criterion) {
+    let mut group = c.benchmark_group("RbOkvs inner product");
+    let values: Vec<_> = random_pairs::<N>(128).into_iter().map(|(_, v)| v).collect();
+    let bytes = ByteValues::from_bytes(&values.to_bytes(), values.len(), N).unwrap();
+    let band: u128 = rand::rng().random();
+    let words = [band as u64, (band >> 64) as u64, 0, 0];
+
+    group.bench_function(format!("OkvsValue<{}> band=128", N), |b| {
+        b.iter(|| black_box(&band).inner_product(&values));
+    });
+    group.bench_function(format!("Bytes({}) band=128", N), |b| {
+        b.iter(|| bytes.inner_product(black_box(&band), 0));
+    });
+    assert_eq!(
+        band.inner_product(&values),
+        baseline::inner_product(&words, &values)
+    );
+    group.bench_function(format!("baseline OkvsValue<{}> band=128", N), |b| {
+        b.iter(|| baseline::inner_product(black_box(&words), &values));
+    });
+    group.finish();
+}
+
+fn bench_value_sizes(c: &mut Criterion) {
+    bench_decode_value_size::<8>(c, 1024);
+    bench_decode_value_size::<1024>(c, 1024);
+    bench_inner_product::<8>(c);
+    bench_inner_product::<1024>(c);
+}
+
+criterion_group!(benches, bench_okvs, bench_value_sizes);
 criterion_main!(benches);
@@ -0,0 +1,263 @@
+//! Binning for the angular distance of vectors, e.g., embeddings compared by cosine
+//! similarity, with random-hyperplane LSH (SimHash).
+//!
+//! Each of L tables draws k random hyperplanes through the origin. The bin of a vector
+//! in a table is the table index together with the k bits that tell on which side of
+//! each hyperplane the vector lies. Two vectors at angle theta fall on the same side
+//! of a random hyperplane with probability 1 - theta / pi, so they share the bin of a
+//! table with probability (1 - theta / pi)^k and share no bin at all with probability
+//! (1 - (1 - theta / pi)^k)^L. Unlike the grid schemes, the binning is probabilistic:
+//! close vectors are missed with this false-negative rate. Both parties use the same
+//! bins ("H_1" = "H_2").
+//!
+//! Vectors are encoded onto the grid with [`Angular::encoder`], which keeps the sign
+//! of the coordinates.
+
+use super::encoding::Encoder;
+use super::grid::{dot, gaussian, Seed};
+use super::{BinKey, BinningScheme, Party, Point};
+use crate::okvs::near_optimal::okvs::OkvsKey;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use std::f64::consts::PI;
+
+/// The signature of a vector in one table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AngularBin {
+    pub table: u16,
+    pub signature: u64,
+}
+
+impl BinKey for AngularBin {
+    const SIZE: usize = 10;
+
+    /// Encodes the table index and the signature in little endian.
+    fn to_key<const N: usize>(&self) -> OkvsKey<N> {
+        assert!(
+            Self::SIZE <= N,
+            "an angular bin needs a key of at least {} bytes, got {}",
+            Self::SIZE,
+            N
+        );
+
+        let mut key = [0u8; N];
+        key[..2].copy_from_slice(&self.table.to_le_bytes());
+        key[2..10].copy_from_slice(&self.signature.to_le_bytes());
+        OkvsKey(key)
+    }
+}
+
+/// Angle between two vectors in radians. The angle to the zero vector is pi.
+pub fn angle<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    let norms = (dot(a, a) * dot(b, b)).sqrt();
+    if norms == 0.0 {
+        return PI;
+    }
+    (dot(a, b) / norms).clamp(-1.0, 1.0).acos()
+}
+
+/// Final check of the protocol for the angular distance: whether the angle between
+/// the two vectors is at most the threshold.
+pub fn is_close<const D: usize>(a: &[f64; D], b: &[f64; D], threshold: f64) -> bool {
+    angle(a, b) <= threshold
+}
+
+/// The probability that two vectors at the given angle share no bin.
+pub fn false_negative_rate(angle: f64, bits: usize, tables: usize) -> f64 {
+    let collision = (1.0 - angle / PI).powi(bits as i32);
+    (1.0 - collision).powi(tables as i32)
+}
+
+/// Random-hyperplane binning for vectors of dimension D that are at most `threshold`
+/// radians apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Angular<const D: usize> {
+    threshold: f64,
+    /// The k hyperplanes of each table, given by their normal vectors.
+    planes: Vec<Vec<[f64; D]>>,
+}
+
+impl<const D: usize> Angular<D> {
+    /// Uses as many tables as needed so that vectors within the threshold share no bin
+    /// with probability at most `false_negative_rate`. More bits per table make the
+    /// bins smaller but need more tables.
+    /// Panics if the threshold is not in `0..pi`, the bits are not in `1..=64`, or the
+    /// rate is not in `0..1`.
+    pub fn new(threshold: f64, bits: usize, false_negative_rate: f64, seed: Seed) -> Self {
+        assert!(
+            false_negative_rate > 0.0 && false_negative_rate < 1.0,
+            "the false-negative rate must be between 0 and 1, got {}",
+            false_negative_rate
+        );
+        assert!(
+            threshold > 0.0 && threshold < PI,
+            "the threshold must be between 0 and pi, got {}",
+            threshold
+        );
+
+        let collision = (1.0 - threshold / PI).powi(bits as i32);
+        let tables = (false_negative_rate.ln() / (1.0 - collision).ln()).ceil();
+        Self::with_tables(threshold, bits, tables.max(1.0) as usize, seed)
+    }
+
+    /// Uses the given number of tables with `bits` hyperplanes each.
+    /// Panics if the threshold is not in `0..=pi`, the bits are not in `1..=64`, or
+    /// the tables are not in `1..=65536`.
+    pub fn with_tables(threshold: f64, bits: usize, tables: usize, seed: Seed) -> Self {
+        assert!(
+            (0.0..=PI).contains(&threshold),
+            "the threshold must be between 0 and pi, got {}",
+            threshold
+        );
+        assert!(
+            (1..=64).contains(&bits),
+            "the signature must have between 1 and 64 bits, got {}",
+            bits
+        );
+        assert!(
+            (1..=u16::MAX as usize + 1).contains(&tables),
+            "the number of tables must be between 1 and {}, got {}",
+            u16::MAX as usize + 1,
+            tables
+        );
+
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        // Use a separate stream, so the planes are independent of a grid from the seed.
+        rng.set_stream(2);
+        let planes = (0..tables)
+            .map(|_| {
+                (0..bits)
+                    .map(|_| std::array::from_fn(|_| gaussian(&mut rng)))
+                    .collect()
+            })
+            .collect();
+
+        Self { threshold, planes }
+    }
+
+    /// The encoder from vectors to the points of the scheme. Coordinates between
+    /// -2^31 and 2^31 are kept with a resolution of 2^-32.
+    pub fn encoder() -> Encoder<f64, D> {
+        Encoder::new([-2f64.powi(31); D], 2f64.powi(-32))
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    pub fn bits(&self) -> usize {
+        self.planes[0].len()
+    }
+
+    pub fn tables(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// The probability that two vectors at the threshold angle share no bin.
+    /// Vectors at a smaller angle are missed less often.
+    pub fn false_negative_rate(&self) -> f64 {
+        false_negative_rate(self.threshold, self.bits(), self.tables())
+    }
+
+    /// The signature of a vector in every table.
+    pub fn signatures(&self, vector: &[f64; D]) -> Vec<AngularBin> {
+        self.planes
+            .iter()
+            .enumerate()
+            .map(|(table, planes)| {
+                let signature = planes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, plane)| dot(plane, vector) >= 0.0)
+                    .fold(0u64, |signature, (bit, _)| signature | 1 << bit);
+                AngularBin {
+                    table: table as u16,
+                    signature,
+                }
+            })
+            .collect()
+    }
+}
+
+impl<const D: usize> BinningScheme for Angular<D> {
+    type Point = Point<D>;
+    type Bin = AngularBin;
+
+    fn expand(&self, point: &Point<D>) -> Vec<AngularBin> {
+        self.signatures(&Self::encoder().decode(point))
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<AngularBin> {
+        self.expand(point)
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        let encoder = Self::encoder();
+        is_close(&encoder.decode(a), &encoder.decode(b), self.threshold)
+    }
+
+    /// Both parties get one bin per table.
+    fn max_bins(&self, _party: Party) -> Option<usize> {
+        Some(self.tables())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::missed_pairs;
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_angle() {
+        assert!((angle(&[1.0, 0.0], &[0.0, 2.0]) - PI / 2.0).abs() < 1e-12);
+        assert!((angle(&[1.0, 1.0], &[-3.0, -3.0]) - PI).abs() < 1e-6);
+        assert_eq!(angle(&[1.0, 0.0], &[0.0, 0.0]), PI);
+        assert!(is_close(&[1.0, 0.0], &[1.0, 0.1], 0.1));
+        assert!(!is_close(&[1.0, 0.0], &[1.0, 0.2], 0.1));
+    }
+
+    #[test]
+    fn test_encoding_keeps_direction() {
+        let encoder = Angular::<3>::encoder();
+        let vector = [0.25, -0.5, 0.75];
+        let decoded = encoder.decode(&encoder.encode(&vector));
+        assert!(angle(&vector, &decoded) < 1e-6);
+    }
+
+    #[test]
+    fn test_tables_from_false_negative_rate() {
+        let scheme = Angular::<4>::new(0.2, 8, 0.01, [1u8; 32]);
+        assert!(scheme.false_negative_rate() <= 0.01);
+        let fewer = Angular::<4>::with_tables(0.2, 8, scheme.tables() - 1, [1u8; 32]);
+        assert!(fewer.false_negative_rate() > 0.01);
+        assert_eq!(scheme.bits(), 8);
+    }
+
+    #[test]
+    fn test_bin_to_key_is_injective() {
+        let a = AngularBin {
+            table: 1,
+            signature: 0,
+        };
+        let b = AngularBin {
+            table: 0,
+            signature: 1 << 8,
+        };
+        assert_ne!(a.to_key::<10>().0, b.to_key::<10>().0);
+    }
+
+    #[test]
+    fn test_false_negative_rate() {
+        let scheme = Angular::<16>::new(0.3, 6, 0.05, [2u8; 32]);
+        let encoder = Angular::<16>::encoder();
+
+        let missed = missed_pairs(&scheme, 500, |rng| {
+            let x: [f64; 16] = std::array::from_fn(|_| gaussian(rng));
+            let y: [f64; 16] = std::array::from_fn(|i| x[i] + 0.3 * rng.random::<f64>() - 0.15);
+            (encoder.encode(&x), encoder.encode(&y))
+        });
+        // at most 5% at the threshold, fewer for smaller angles
+        assert!(missed.len() <= 25, "missed {} of 500", missed.len());
+    }
+}
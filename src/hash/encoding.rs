@@ -0,0 +1,149 @@
+//! Encoding of signed and fractional coordinates into the unsigned grid that the
+//! binning schemes work on. A coordinate `v` is mapped to the number of grid steps
+//! between an origin and `v`, i.e., `(v - origin) / resolution` rounded to the nearest
+//! step. The distance threshold is converted with the same resolution, so the same
+//! conversion feeds the binning and the final distance check, and delta means the
+//! same in user units as on the grid (up to the rounding to the resolution).
+
+use super::Point;
+use std::fmt::Debug;
+
+/// Coordinates in user units that can be encoded into grid steps.
+pub trait Coordinate: Copy + Debug + Default + PartialOrd {
+    /// The number of steps of size `resolution` from `origin` to the coordinate,
+    /// rounded to the nearest step and clamped to `0..=u64::MAX`.
+    fn to_steps(self, origin: Self, resolution: Self) -> u64;
+
+    /// The coordinate that lies the given number of steps after `origin`.
+    fn from_steps(steps: u64, origin: Self, resolution: Self) -> Self;
+}
+
+impl Coordinate for i64 {
+    fn to_steps(self, origin: i64, resolution: i64) -> u64 {
+        let diff = self as i128 - origin as i128;
+        let resolution = resolution as i128;
+        // rounds half up: floor((diff + resolution / 2) / resolution)
+        let steps = (2 * diff + resolution).div_euclid(2 * resolution);
+        steps.clamp(0, u64::MAX as i128) as u64
+    }
+
+    fn from_steps(steps: u64, origin: i64, resolution: i64) -> i64 {
+        let value = origin as i128 + steps as i128 * resolution as i128;
+        value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+}
+
+impl Coordinate for f64 {
+    /// Panics if the coordinate is NaN.
+    fn to_steps(self, origin: f64, resolution: f64) -> u64 {
+        assert!(!self.is_nan(), "cannot encode a NaN coordinate");
+        // the cast saturates at 0 and u64::MAX
+        ((self - origin) / resolution).round() as u64
+    }
+
+    fn from_steps(steps: u64, origin: f64, resolution: f64) -> f64 {
+        origin + steps as f64 * resolution
+    }
+}
+
+/// Encodes points of D coordinates in user units into grid points.
+/// The origin is given per dimension, the resolution is the same in all dimensions,
+/// so distances are scaled equally in every direction.
+///
+/// For example, `Encoder::new([i64::MIN; D], 1)` maps all of `i64` onto `u64`
+/// without loss, and `Encoder::new([-180.0, -90.0], 0.001)` maps degrees with a
+/// resolution of a thousandth of a degree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Encoder<C, const D: usize> {
+    origin: [C; D],
+    resolution: C,
+}
+
+impl<C: Coordinate, const D: usize> Encoder<C, D> {
+    /// Panics if the resolution is not positive.
+    pub fn new(origin: [C; D], resolution: C) -> Self {
+        assert!(
+            resolution > C::default(),
+            "the resolution must be positive, got {:?}",
+            resolution
+        );
+        Self { origin, resolution }
+    }
+
+    pub fn origin(&self) -> [C; D] {
+        self.origin
+    }
+
+    pub fn resolution(&self) -> C {
+        self.resolution
+    }
+
+    /// The grid point of a point in user units.
+    pub fn encode(&self, point: &[C; D]) -> Point<D> {
+        std::array::from_fn(|dim| point[dim].to_steps(self.origin[dim], self.resolution))
+    }
+
+    /// The grid points of a set of points in user units.
+    pub fn encode_all(&self, points: &[[C; D]]) -> Vec<Point<D>> {
+        points.iter().map(|point| self.encode(point)).collect()
+    }
+
+    /// The point in user units at the given grid point.
+    pub fn decode(&self, point: &Point<D>) -> [C; D] {
+        std::array::from_fn(|dim| C::from_steps(point[dim], self.origin[dim], self.resolution))
+    }
+
+    /// The distance threshold on the grid for a threshold in user units. It is the
+    /// number of steps of the distance, rounded like the coordinates.
+    pub fn encode_delta(&self, delta: C) -> u64 {
+        delta.to_steps(C::default(), self.resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_coordinates() {
+        let encoder = Encoder::new([i64::MIN, -100], 1);
+        assert_eq!(encoder.encode(&[i64::MIN, -100]), [0, 0]);
+        assert_eq!(encoder.encode(&[-1, 5]), [i64::MAX as u64, 105]);
+        assert_eq!(encoder.encode(&[i64::MAX, -101]), [u64::MAX, 0]);
+        assert_eq!(encoder.decode(&encoder.encode(&[-42, 7])), [-42, 7]);
+        assert_eq!(encoder.encode_delta(3), 3);
+    }
+
+    #[test]
+    fn test_integer_resolution() {
+        let encoder = Encoder::new([0], 10);
+        assert_eq!(encoder.encode(&[14]), [1]);
+        assert_eq!(encoder.encode(&[15]), [2]);
+        assert_eq!(encoder.encode(&[-4]), [0]);
+        assert_eq!(encoder.decode(&[2]), [20]);
+        assert_eq!(encoder.encode_delta(25), 3);
+    }
+
+    #[test]
+    fn test_float_coordinates() {
+        let encoder = Encoder::new([-180.0, -90.0], 0.01);
+        assert_eq!(encoder.encode(&[-180.0, 0.0]), [0, 9000]);
+        assert_eq!(encoder.encode(&[0.004, 0.006]), [18000, 9001]);
+        assert_eq!(encoder.encode(&[-200.0, f64::INFINITY]), [0, u64::MAX]);
+        let decoded = encoder.decode(&[18050, 9000]);
+        assert!((decoded[0] - 0.5).abs() < 1e-9 && decoded[1].abs() < 1e-9);
+        assert_eq!(encoder.encode_delta(0.5), 50);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_nan_coordinate() {
+        Encoder::new([0.0], 1.0).encode(&[f64::NAN]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_non_positive_resolution() {
+        Encoder::new([0i64], 0);
+    }
+}
@@ -0,0 +1,227 @@
+//! Binning for geographic coordinates with a threshold in meters, e.g., for
+//! co-location or contact tracing.
+//!
+//! Points are latitude/longitude pairs encoded in steps of 10^-7 degrees (about 1 cm)
+//! with [`Geo::encoder`]. The globe is split into latitude bands of height 2 * delta.
+//! Each band is projected with its own local equirectangular projection, i.e., its
+//! longitude cells are 2 * delta wide a little poleward of the band, so the cells are
+//! roughly square in meters and the distortion is bounded by the band height.
+//! Alice expands her point into every cell that can contain a point within delta
+//! meters, Bob uses the cell of his point, and the final check uses the haversine
+//! distance.
+
+use super::encoding::Encoder;
+use super::grid::{Domain, Grid};
+use super::{Bin, BinningScheme, Party, Point};
+
+/// Mean radius of the earth in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Number of encoding steps per degree.
+const STEPS_PER_DEGREE: f64 = 1e7;
+
+/// Number of encoding steps around the globe in longitude.
+const LONGITUDE_STEPS: u64 = 360 * STEPS_PER_DEGREE as u64;
+
+/// Number of encoding steps from the south pole to the north pole, inclusive.
+const LATITUDE_STEPS: u64 = 180 * STEPS_PER_DEGREE as u64 + 1;
+
+/// Great-circle distance in meters between two points given as (latitude, longitude)
+/// in degrees.
+pub fn haversine(a: &[f64; 2], b: &[f64; 2]) -> f64 {
+    let (lat_a, lat_b) = (a[0].to_radians(), b[0].to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b[1] - a[1]).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().min(1.0).asin()
+}
+
+/// Asymmetric binning of geographic points for a threshold of delta meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geo {
+    meters: f64,
+    /// The threshold as an angle in radians.
+    angle: f64,
+    /// The latitude bands, with delta in encoding steps.
+    bands: Grid<1>,
+}
+
+impl Geo {
+    /// Panics if the threshold is not positive or reaches around the globe.
+    pub fn new(meters: f64) -> Self {
+        let angle = meters / EARTH_RADIUS;
+        assert!(
+            angle > 0.0 && angle < std::f64::consts::PI,
+            "the threshold must be between 0 and half the circumference, got {} m",
+            meters
+        );
+
+        // One step of slack covers the rounding of the encoding.
+        let steps = (angle.to_degrees() * STEPS_PER_DEGREE).ceil() as u64 + 1;
+        Self {
+            meters,
+            angle,
+            bands: Grid::new(steps).with_domain(Domain::Clamped(LATITUDE_STEPS)),
+        }
+    }
+
+    /// The encoder from (latitude, longitude) in degrees to the points of the scheme.
+    pub fn encoder() -> Encoder<f64, 2> {
+        Encoder::new([-90.0, -180.0], 1.0 / STEPS_PER_DEGREE)
+    }
+
+    pub fn meters(&self) -> f64 {
+        self.meters
+    }
+
+    /// The longitude cells of a band, wrapping around at the antimeridian. A cell is
+    /// twice as wide as the longitude interval of any point that touches the band,
+    /// i.e., of a point up to delta beyond the poleward edge of the band.
+    fn longitudes(&self, band: u64) -> Grid<1> {
+        let height = 2 * self.bands.delta();
+        let south = (band * height) as f64 / STEPS_PER_DEGREE - 90.0;
+        let north = ((band + 1) * height) as f64 / STEPS_PER_DEGREE - 90.0;
+        let reach = self.bands.delta() as f64 / STEPS_PER_DEGREE;
+        let poleward = (south.abs().max(north.abs()) + reach).min(90.0);
+        let delta = self.longitude_radius(poleward);
+        Grid::new(delta).with_domain(Domain::Cyclic(LONGITUDE_STEPS))
+    }
+
+    /// The largest longitude difference in encoding steps between the point and any
+    /// point within delta meters. The parallels are shortest at the latitude closest
+    /// to a pole that such a point can have.
+    fn longitude_radius(&self, latitude: f64) -> u64 {
+        let polar = (latitude.abs().to_radians() + self.angle).min(std::f64::consts::FRAC_PI_2);
+        let ratio = (self.angle / 2.0).sin() / polar.cos();
+        if ratio >= 1.0 {
+            return LONGITUDE_STEPS;
+        }
+        let radius = 2.0 * ratio.asin();
+        (radius.to_degrees() * STEPS_PER_DEGREE * (1.0 + 1e-9)).ceil() as u64 + 1
+    }
+}
+
+impl BinningScheme for Geo {
+    type Point = Point<2>;
+    type Bin = Bin<2>;
+
+    fn expand(&self, point: &Point<2>) -> Vec<Bin<2>> {
+        let latitude = Self::encoder().decode(point)[0];
+        let radius = self.longitude_radius(latitude);
+
+        let mut bins = Vec::new();
+        for band in self.bands.cell_range(0, point[0], self.bands.delta()) {
+            for cell in self.longitudes(band).cell_range(0, point[1], radius) {
+                bins.push(Bin([band, cell]));
+            }
+        }
+        bins
+    }
+
+    fn assign(&self, point: &Point<2>) -> Vec<Bin<2>> {
+        let band = self.bands.cell_of(0, point[0]);
+        vec![Bin([band, self.longitudes(band).cell_of(0, point[1])])]
+    }
+
+    fn is_close(&self, a: &Point<2>, b: &Point<2>) -> bool {
+        let encoder = Self::encoder();
+        haversine(&encoder.decode(a), &encoder.decode(b)) <= self.meters
+    }
+
+    /// Alice's latitude interval touches at most two bands. In each band, her
+    /// longitude interval is at most as wide as a cell, so it touches at most two
+    /// cells, or three next to the shorter last cell at the antimeridian.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        match party {
+            Party::Alice => Some(6),
+            Party::Bob => Some(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_conditional_overlap;
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_haversine() {
+        // one degree of latitude
+        let degree = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+        assert!((haversine(&[10.0, 20.0], &[11.0, 20.0]) - degree).abs() < 1e-6);
+        // one degree of longitude at 60 degrees is about half as long
+        assert!((haversine(&[60.0, 20.0], &[60.0, 21.0]) - degree / 2.0).abs() < 10.0);
+        // across the antimeridian
+        assert!((haversine(&[0.0, 179.5], &[0.0, -179.5]) - degree).abs() < 1e-6);
+        // Paris to London
+        let distance = haversine(&[48.8566, 2.3522], &[51.5074, -0.1278]);
+        assert!((distance - 343_500.0).abs() < 1_000.0);
+    }
+
+    #[test]
+    fn test_encoding_round_trip() {
+        let encoder = Geo::encoder();
+        let point = encoder.encode(&[47.3769, 8.5417]);
+        let decoded = encoder.decode(&point);
+        assert!(haversine(&decoded, &[47.3769, 8.5417]) < 0.01);
+    }
+
+    #[test]
+    fn test_few_bins() {
+        let scheme = Geo::new(100.0);
+        let encoder = Geo::encoder();
+        for point in [
+            [0.0, 0.0],
+            [47.3769, 8.5417],
+            [-33.8688, 151.2093],
+            [70.0, 25.0],
+        ] {
+            let bins = scheme.expand(&encoder.encode(&point));
+            assert!(bins.len() <= 6, "{:?} has {} bins", point, bins.len());
+        }
+    }
+
+    /// A point up to `spread` degrees from `around` and a point in a box of about
+    /// delta meters around it.
+    fn nearby_pair(
+        rng: &mut ChaCha20Rng,
+        scheme: &Geo,
+        around: [f64; 2],
+        spread: f64,
+    ) -> (Point<2>, Point<2>) {
+        let encoder = Geo::encoder();
+        let x = [
+            (around[0] + rng.random_range(-spread..spread)).clamp(-90.0, 90.0),
+            around[1] + rng.random_range(-spread..spread),
+        ];
+        let step = scheme.meters() / EARTH_RADIUS;
+        let y = [
+            (x[0] + rng.random_range(-step..step).to_degrees()).clamp(-90.0, 90.0),
+            x[1] + rng.random_range(-step..step).to_degrees() / x[0].to_radians().cos(),
+        ];
+        let wrap = |lon: f64| (lon + 540.0).rem_euclid(360.0) - 180.0;
+        (
+            encoder.encode(&[x[0], wrap(x[1])]),
+            encoder.encode(&[y[0], wrap(y[1])]),
+        )
+    }
+
+    fn assert_overlap_around(scheme: &Geo, around: [f64; 2], spread: f64) {
+        assert_conditional_overlap(scheme, |rng| nearby_pair(rng, scheme, around, spread));
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        let scheme = Geo::new(50.0);
+        assert_overlap_around(&scheme, [47.3769, 8.5417], 0.01);
+        assert_overlap_around(&scheme, [0.0, 0.0], 0.01);
+        assert_overlap_around(&scheme, [-60.0, -70.0], 1.0);
+        // antimeridian and poles
+        assert_overlap_around(&scheme, [10.0, 180.0], 0.001);
+        assert_overlap_around(&scheme, [89.9995, 0.0], 0.001);
+        assert_overlap_around(&scheme, [-89.9995, 0.0], 0.001);
+        assert_overlap_around(&Geo::new(20_000.0), [80.0, 179.0], 1.0);
+    }
+}
@@ -0,0 +1,445 @@
+//! The grid over Z^d with cells of side length 2 * delta that the l_p binning schemes
+//! are built on. The grid is either anchored at 0 or shifted by a random offset per
+//! dimension. The offset is derived from a seed that both parties share, so both
+//! parties use the same grid, but the bin layout is different in every session.
+//!
+//! The coordinates live either on the unbounded `u64` line or in a bounded domain
+//! Z_m, which is clamped at its ends or wraps around (e.g., angles or time of day).
+
+use super::{cartesian, Bin, Point};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+/// Seed that both parties agree on per session to derive the same random grid.
+pub type Seed = [u8; 32];
+
+/// The values a single coordinate can take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Domain {
+    /// The whole `u64` line. Intervals are clamped at `0` and `u64::MAX`.
+    Unbounded,
+    /// Z_m = `0..m`. Larger coordinates are clamped to `m - 1` and intervals are
+    /// clamped at `0` and `m - 1`.
+    Clamped(u64),
+    /// Z_m = `0..m` as a cycle, i.e., `m - 1` and `0` are neighbours. Larger
+    /// coordinates are reduced modulo m.
+    Cyclic(u64),
+}
+
+impl Domain {
+    /// Maps a coordinate into the domain.
+    pub fn normalize(&self, val: u64) -> u64 {
+        match *self {
+            Domain::Unbounded => val,
+            Domain::Clamped(m) => val.min(m - 1),
+            Domain::Cyclic(m) => val % m,
+        }
+    }
+
+    /// Distance between two coordinates, the shorter way around for a cyclic domain.
+    pub fn distance(&self, a: u64, b: u64) -> u64 {
+        let diff = self.normalize(a).abs_diff(self.normalize(b));
+        match *self {
+            Domain::Cyclic(m) => diff.min(m - diff),
+            _ => diff,
+        }
+    }
+}
+
+/// The grid of cells with side length 2 * delta, shifted by `offset`.
+/// A coordinate `v` lies in the cell `(v + offset) / (2 * delta)`. In a cyclic domain,
+/// the shifted coordinate is reduced modulo m, so the last cell may be shorter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid<const D: usize> {
+    delta: u64,
+    offset: [u128; D],
+    domain: Domain,
+}
+
+impl<const D: usize> Grid<D> {
+    /// The grid anchored at 0. Panics if delta is 0, as cells of side length 0 cannot
+    /// hold any point; exact matching needs no grid.
+    pub fn new(delta: u64) -> Self {
+        assert!(
+            delta > 0,
+            "the grid needs delta > 0, exact matching is not supported"
+        );
+        Self {
+            delta,
+            offset: [0u128; D],
+            domain: Domain::Unbounded,
+        }
+    }
+
+    /// The grid shifted by a random offset in `0..2 * delta` per dimension,
+    /// derived from the shared seed. The offset is a u128 like the side length, so
+    /// any delta fits. Panics if delta is 0, like [`Grid::new`].
+    pub fn shifted(delta: u64, seed: Seed) -> Self {
+        assert!(
+            delta > 0,
+            "the grid needs delta > 0, exact matching is not supported"
+        );
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        let side = 2 * delta as u128;
+        Self {
+            delta,
+            offset: std::array::from_fn(|_| rng.random_range(0..side)),
+            domain: Domain::Unbounded,
+        }
+    }
+
+    /// The same grid over the given domain. Panics if the domain is empty.
+    pub fn with_domain(self, domain: Domain) -> Self {
+        if let Domain::Clamped(m) | Domain::Cyclic(m) = domain {
+            assert!(m > 0, "the domain Z_m must not be empty");
+        }
+        Self { domain, ..self }
+    }
+
+    pub fn delta(&self) -> u64 {
+        self.delta
+    }
+
+    pub fn offset(&self) -> [u128; D] {
+        self.offset
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    fn side(&self) -> u128 {
+        2 * self.delta as u128
+    }
+
+    /// The shifted coordinate, reduced modulo m in a cyclic domain.
+    /// It is computed in u128, thus it cannot overflow.
+    fn shift(&self, dim: usize, val: u64) -> u128 {
+        let shifted = self.domain.normalize(val) as u128 + self.offset[dim];
+        match self.domain {
+            Domain::Cyclic(m) => shifted % m as u128,
+            _ => shifted,
+        }
+    }
+
+    /// Index of the cell that contains the coordinate in the given dimension.
+    pub fn cell_of(&self, dim: usize, val: u64) -> u64 {
+        (self.shift(dim, val) / self.side()) as u64
+    }
+
+    /// "H_2": The cell that contains the point.
+    pub fn cell(&self, point: &Point<D>) -> Bin<D> {
+        Bin(std::array::from_fn(|dim| self.cell_of(dim, point[dim])))
+    }
+
+    /// The cells that are touched by the interval `val - radius..=val + radius` of a
+    /// single coordinate. On a line, the cells are computed from the interval bounds
+    /// and the interval is clamped to the domain. On a cycle, the interval wraps
+    /// around and the cells are walked from the lower bound, so the cost only depends
+    /// on the number of cells.
+    pub fn cell_range(&self, dim: usize, val: u64, radius: u64) -> Vec<u64> {
+        let val = self.domain.normalize(val);
+        let top = match self.domain {
+            Domain::Unbounded => u64::MAX,
+            Domain::Clamped(m) => m - 1,
+            Domain::Cyclic(m) => return self.cyclic_cell_range(dim, val, radius, m),
+        };
+
+        let first = self.cell_of(dim, val.saturating_sub(radius));
+        let last = self.cell_of(dim, val.saturating_add(radius).min(top));
+        (first..=last).collect()
+    }
+
+    fn cyclic_cell_range(&self, dim: usize, val: u64, radius: u64, m: u64) -> Vec<u64> {
+        let m = m as u128;
+        let cells = m.div_ceil(self.side());
+        // The interval covers 2 * radius + 1 positions, or the whole cycle.
+        let mut remaining = (2 * radius as u128 + 1).min(m);
+        let mut position = (self.shift(dim, val) + m - radius as u128 % m) % m;
+
+        let mut range = Vec::new();
+        while remaining > 0 && (range.len() as u128) < cells {
+            let cell = position / self.side();
+            let end = ((cell + 1) * self.side()).min(m);
+            range.push(cell as u64);
+            remaining = remaining.saturating_sub(end - position);
+            position = end % m;
+        }
+        range
+    }
+
+    /// Distance between a single coordinate and the interval covered by the given cell.
+    /// Zero if the coordinate lies in the cell.
+    pub fn cell_distance(&self, dim: usize, val: u64, cell: u64) -> u64 {
+        let lower = cell as i128 * self.side() as i128;
+        let upper = lower + self.side() as i128 - 1;
+
+        if let Domain::Cyclic(m) = self.domain {
+            let m = m as i128;
+            let upper = upper.min(m - 1);
+            let val = self.shift(dim, val) as i128;
+            if (lower..=upper).contains(&val) {
+                return 0;
+            }
+            let around = |a: i128, b: i128| (a - b).abs().min(m - (a - b).abs());
+            return around(val, lower).min(around(val, upper)) as u64;
+        }
+
+        let val = self.shift(dim, val) as i128;
+        let distance = if val < lower {
+            lower - val
+        } else {
+            (val - upper).max(0)
+        };
+        distance.min(u64::MAX as i128) as u64
+    }
+
+    /// The largest number of cells that the interval `val - delta..=val + delta` of a
+    /// single coordinate touches. The interval is one position longer than a cell, so
+    /// it touches two cells, or three in a cyclic domain whose last cell is shorter.
+    pub fn max_cells(&self) -> usize {
+        match self.domain {
+            Domain::Cyclic(m) => {
+                let m = m as u128;
+                let cells = m.div_ceil(self.side());
+                let most = if m.is_multiple_of(self.side()) { 2 } else { 3 };
+                cells.min(most) as usize
+            }
+            _ => 2,
+        }
+    }
+
+    /// The largest number of cells that a ball around a point touches, if moving into
+    /// another cell costs at least 1 of the budget per dimension, e.g., the l_1 ball
+    /// with budget delta or the l_2 ball with budget delta^2. At most `budget`
+    /// dimensions leave the cell of the point, each into one of the other cells
+    /// counted by [`Grid::max_cells`]. Returns `None` if the number does not fit into
+    /// a `usize`.
+    pub fn max_cells_in_ball(&self, budget: u128) -> Option<usize> {
+        let others = self.max_cells() as u128 - 1;
+        let most = budget.min(D as u128) as usize;
+
+        // sum over k of binomial(D, k) * others^k
+        let mut total = 0u128;
+        let mut binomial = 1u128;
+        for k in 0..=most {
+            total = total.checked_add(binomial.checked_mul(others.checked_pow(k as u32)?)?)?;
+            if k < most {
+                binomial = binomial.checked_mul((D - k) as u128)? / (k as u128 + 1);
+            }
+        }
+        usize::try_from(total).ok()
+    }
+
+    /// Distance between two points per coordinate, measured in the domain.
+    pub fn distances(&self, a: &Point<D>, b: &Point<D>) -> [u64; D] {
+        std::array::from_fn(|dim| self.domain.distance(a[dim], b[dim]))
+    }
+
+    /// "H_1" for the d_infinity metric: All cells that contain a position within
+    /// distance delta of the point.
+    pub fn cells_around(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let cells: [Vec<u64>; D] =
+            std::array::from_fn(|dim| self.cell_range(dim, point[dim], self.delta));
+        cartesian(&cells)
+    }
+}
+
+/// A random rotation of R^d, i.e., an orthogonal d x d matrix, derived from a seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation<const D: usize>([[f64; D]; D]);
+
+impl<const D: usize> Rotation<D> {
+    /// Samples a matrix with standard normal entries and orthonormalizes its rows
+    /// with Gram-Schmidt, which gives a uniformly random orthogonal matrix.
+    pub fn from_seed(seed: Seed) -> Self {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        // Use a separate stream, so the rotation is independent of the grid offset.
+        rng.set_stream(1);
+
+        let mut rows = [[0f64; D]; D];
+        let mut i = 0;
+        while i < D {
+            let mut row: [f64; D] = std::array::from_fn(|_| gaussian(&mut rng));
+            for previous in &rows[..i] {
+                let projection = dot(&row, previous);
+                for (r, p) in row.iter_mut().zip(previous) {
+                    *r -= projection * p;
+                }
+            }
+            let norm = dot(&row, &row).sqrt();
+            // Retry in the (practically impossible) case of a degenerate sample.
+            if norm < 1e-6 {
+                continue;
+            }
+            rows[i] = row.map(|r| r / norm);
+            i += 1;
+        }
+
+        Self(rows)
+    }
+
+    /// Rotates the point.
+    pub fn apply(&self, point: &[f64; D]) -> [f64; D] {
+        std::array::from_fn(|i| dot(&self.0[i], point))
+    }
+}
+
+pub(crate) fn dot<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Standard normal sample with the Box-Muller transform.
+pub(crate) fn gaussian(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchored_grid() {
+        let grid = Grid::<1>::new(2);
+        assert_eq!(grid.cell(&[8]), Bin([2]));
+        assert_eq!(grid.cell_range(0, 8, 2), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_cell_distance() {
+        // cell 2 with delta 2 covers 8..=11
+        let grid = Grid::<1>::new(2);
+        assert_eq!(grid.cell_distance(0, 5, 2), 3);
+        assert_eq!(grid.cell_distance(0, 9, 2), 0);
+        assert_eq!(grid.cell_distance(0, 14, 2), 3);
+    }
+
+    #[test]
+    fn test_shifted_grid() {
+        let grid = Grid::<2> {
+            delta: 2,
+            offset: [1, 3],
+            domain: Domain::Unbounded,
+        };
+        // x: (8 + 1) / 4 = 2, y: (8 + 3) / 4 = 2
+        assert_eq!(grid.cell(&[8, 8]), Bin([2, 2]));
+        // cell 2 covers 7..=10 in x and 5..=8 in y
+        assert_eq!(grid.cell_distance(0, 6, 2), 1);
+        assert_eq!(grid.cell_distance(0, 10, 2), 0);
+        assert_eq!(grid.cell_distance(1, 9, 2), 1);
+        // cell 0 covers 0..=2 in x
+        assert_eq!(grid.cell_distance(0, 0, 0), 0);
+        assert_eq!(grid.cell_distance(0, 4, 0), 2);
+        // no overflow at the top of the domain
+        assert_eq!(
+            grid.cell_of(1, u64::MAX),
+            (u64::MAX as u128).div_ceil(4) as u64
+        );
+    }
+
+    #[test]
+    fn test_clamped_domain() {
+        let grid = Grid::<1>::new(2).with_domain(Domain::Clamped(10));
+        // cells: 0..=3, 4..=7, 8..=9
+        assert_eq!(grid.cell_range(0, 9, 2), vec![1, 2]);
+        assert_eq!(grid.cell_range(0, 1, 2), vec![0]);
+        // coordinates outside of the domain are clamped to 9
+        assert_eq!(grid.cell_of(0, 100), 2);
+        assert_eq!(Domain::Clamped(10).distance(7, 100), 2);
+    }
+
+    #[test]
+    fn test_cyclic_domain() {
+        let domain = Domain::Cyclic(360);
+        assert_eq!(domain.distance(359, 1), 2);
+        assert_eq!(domain.distance(1, 359), 2);
+        assert_eq!(domain.distance(0, 180), 180);
+        assert_eq!(domain.distance(720, 3), 3);
+
+        let grid = Grid::<1>::new(2).with_domain(domain);
+        // 359 is within distance 2 of the cell 0..=3 across the end of the cycle
+        assert_eq!(grid.cell_range(0, 359, 2), vec![89, 0]);
+        assert_eq!(grid.cell_distance(0, 359, 0), 1);
+        assert_eq!(grid.cell_distance(0, 1, 89), 2);
+        assert_eq!(grid.cell_distance(0, 180, 0), 177);
+    }
+
+    #[test]
+    fn test_cyclic_cell_range_matches_cell_distance() {
+        // m is not a multiple of the side length, so the last cell is shorter.
+        for m in [1u64, 5, 10, 11, 23] {
+            let grid = Grid::<1>::shifted(2, [4u8; 32]).with_domain(Domain::Cyclic(m));
+            let cells = m.div_ceil(4);
+            for val in 0..m {
+                for radius in 0..=m {
+                    let mut range = grid.cell_range(0, val, radius);
+                    range.sort();
+                    let expected: Vec<u64> = (0..cells)
+                        .filter(|&cell| grid.cell_distance(0, val, cell) <= radius)
+                        .collect();
+                    assert_eq!(range, expected, "m {} val {} radius {}", m, val, radius);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "delta > 0")]
+    fn test_zero_delta() {
+        Grid::<2>::new(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "delta > 0")]
+    fn test_zero_delta_shifted() {
+        Grid::<2>::shifted(0, [1u8; 32]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_empty_domain() {
+        Grid::<1>::new(2).with_domain(Domain::Cyclic(0));
+    }
+
+    #[test]
+    fn test_shifted_grid_from_seed() {
+        let delta = 1000;
+        let a = Grid::<3>::shifted(delta, [7u8; 32]);
+        let b = Grid::<3>::shifted(delta, [7u8; 32]);
+        let c = Grid::<3>::shifted(delta, [8u8; 32]);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.offset().iter().all(|&o| o < 2 * delta as u128));
+    }
+
+    #[test]
+    fn test_shifted_grid_large_delta() {
+        let delta = u64::MAX / 2 + 1;
+        let grid = Grid::<2>::shifted(delta, [7u8; 32]);
+        assert!(grid.offset().iter().all(|&o| o < 2 * delta as u128));
+        // the whole line fits into two cells
+        let cells = grid.cells_around(&[u64::MAX / 2, 0]);
+        assert!(cells.contains(&grid.cell(&[u64::MAX, 0])));
+        assert!(cells.contains(&grid.cell(&[0, 0])));
+    }
+
+    #[test]
+    fn test_rotation_from_seed() {
+        let a = Rotation::<3>::from_seed([7u8; 32]);
+        assert_eq!(a, Rotation::<3>::from_seed([7u8; 32]));
+        assert_ne!(a, Rotation::<3>::from_seed([8u8; 32]));
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((dot(&a.0[i], &a.0[j]) - expected).abs() < 1e-12);
+            }
+        }
+
+        let point = [3.0, 4.0, 12.0];
+        let rotated = a.apply(&point);
+        assert!((dot(&rotated, &rotated) - 169.0).abs() < 1e-9);
+    }
+}
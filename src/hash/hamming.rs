@@ -0,0 +1,252 @@
+//! Conditionally-overlapping hash functions for the Hamming distance of bit strings.
+//! The binning uses the pigeonhole principle: the first `bits` bits of a string are
+//! split into delta + 1 disjoint chunks. If two strings differ in at most delta bits,
+//! at least one of the chunks is equal in both strings. Each chunk together with its
+//! position is a bin, and both parties use the same bins ("H_1" = "H_2").
+
+use super::{BinKey, BinningScheme, Party};
+use crate::okvs::near_optimal::okvs::OkvsKey;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A bit string of up to `64 * W` bits. Bit `i` is bit `i % 64` of word `i / 64`.
+pub type BitString<const W: usize> = [u64; W];
+
+/// A chunk of a bit string. The bits of the chunk stay at their position in the
+/// string and all other bits are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HammingBin<const W: usize> {
+    pub chunk: u16,
+    pub bits: BitString<W>,
+}
+
+impl<const W: usize> BinKey for HammingBin<W> {
+    const SIZE: usize = 2 + 8 * W;
+
+    /// Encodes the chunk index in little endian followed by the words of the chunk.
+    fn to_key<const N: usize>(&self) -> OkvsKey<N> {
+        assert!(
+            Self::SIZE <= N,
+            "a chunk of {} words needs a key of at least {} bytes, got {}",
+            W,
+            Self::SIZE,
+            N
+        );
+
+        let mut key = [0u8; N];
+        key[..2].copy_from_slice(&self.chunk.to_le_bytes());
+        for (bytes, word) in key[2..].chunks_exact_mut(8).zip(self.bits) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        OkvsKey(key)
+    }
+}
+
+/// A string with the bits in the given range set.
+fn mask<const W: usize>(range: Range<usize>) -> BitString<W> {
+    let mut mask = [0u64; W];
+    for bit in range {
+        mask[bit / 64] |= 1 << (bit % 64);
+    }
+    mask
+}
+
+fn and<const W: usize>(a: &BitString<W>, b: &BitString<W>) -> BitString<W> {
+    std::array::from_fn(|i| a[i] & b[i])
+}
+
+/// The range of bits of each of the delta + 1 chunks of a string with `bits` bits.
+/// The chunks differ in length by at most one bit.
+fn chunks(bits: usize, delta: usize) -> Vec<Range<usize>> {
+    let count = delta + 1;
+    (0..count)
+        .map(|i| (i * bits / count)..((i + 1) * bits / count))
+        .collect()
+}
+
+/// "H_1" and "H_2": This computes the bins of a set of bit strings of length `bits`
+/// for the Hamming distance delta, i.e., every chunk of every string.
+pub fn create_bins<const W: usize>(
+    points: &[BitString<W>],
+    bits: usize,
+    delta: usize,
+) -> HashSet<HammingBin<W>> {
+    Hamming::new(bits, delta)
+        .chunk_masks
+        .iter()
+        .enumerate()
+        .flat_map(|(chunk, mask)| {
+            points.iter().map(move |point| HammingBin {
+                chunk: chunk as u16,
+                bits: and(point, mask),
+            })
+        })
+        .collect()
+}
+
+/// Hamming distance between two bit strings: the number of differing bits.
+pub fn distance<const W: usize>(a: &BitString<W>, b: &BitString<W>) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+/// Final check of the protocol for the Hamming distance: whether the two strings
+/// differ in at most delta bits.
+pub fn is_close<const W: usize>(a: &BitString<W>, b: &BitString<W>, delta: usize) -> bool {
+    distance(a, b) as usize <= delta
+}
+
+/// Binning for the Hamming distance of bit strings with `bits` bits by splitting the
+/// strings into delta + 1 chunks. Bits after the first `bits` bits are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hamming<const W: usize> {
+    bits: usize,
+    delta: usize,
+    chunk_masks: Vec<BitString<W>>,
+}
+
+impl<const W: usize> Hamming<W> {
+    /// Panics if the strings do not fit into W words or if there are not enough bits
+    /// for delta + 1 non-empty chunks.
+    pub fn new(bits: usize, delta: usize) -> Self {
+        assert!(bits <= 64 * W, "{} bits do not fit into {} words", bits, W);
+        assert!(
+            delta < bits && delta <= u16::MAX as usize,
+            "cannot split {} bits into {} chunks",
+            bits,
+            delta + 1
+        );
+
+        Self {
+            bits,
+            delta,
+            chunk_masks: chunks(bits, delta).into_iter().map(mask).collect(),
+        }
+    }
+}
+
+impl<const W: usize> BinningScheme for Hamming<W> {
+    type Point = BitString<W>;
+    type Bin = HammingBin<W>;
+
+    fn expand(&self, point: &BitString<W>) -> Vec<HammingBin<W>> {
+        self.chunk_masks
+            .iter()
+            .enumerate()
+            .map(|(chunk, mask)| HammingBin {
+                chunk: chunk as u16,
+                bits: and(point, mask),
+            })
+            .collect()
+    }
+
+    fn assign(&self, point: &BitString<W>) -> Vec<HammingBin<W>> {
+        self.expand(point)
+    }
+
+    fn is_close(&self, a: &BitString<W>, b: &BitString<W>) -> bool {
+        let relevant = mask::<W>(0..self.bits);
+        is_close(&and(a, &relevant), &and(b, &relevant), self.delta)
+    }
+
+    /// Both parties get one bin per chunk.
+    fn max_bins(&self, _party: Party) -> Option<usize> {
+        Some(self.delta + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_conditional_overlap;
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_chunks() {
+        assert_eq!(chunks(10, 2), vec![0..3, 3..6, 6..10]);
+        assert_eq!(chunks(128, 3), vec![0..32, 32..64, 64..96, 96..128]);
+    }
+
+    #[test]
+    fn test_create_bins() {
+        let bins = create_bins(&[[0b1011_0110u64]], 8, 1);
+        let expected_bins: HashSet<HammingBin<1>> = vec![
+            HammingBin {
+                chunk: 0,
+                bits: [0b0110],
+            },
+            HammingBin {
+                chunk: 1,
+                bits: [0b1011_0000],
+            },
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(&[0b1011u64, 0], &[0b0001u64, 1 << 63]), 3);
+        assert!(is_close(&[0b1011u64], &[0b0001u64], 2));
+        assert!(!is_close(&[0b1011u64], &[0b0100u64], 2));
+    }
+
+    #[test]
+    fn test_scheme_ignores_unused_bits() {
+        let scheme = Hamming::<1>::new(8, 1);
+        let a = [0b1011_0110u64];
+        let b = [0b1011_0111u64 | (0xff << 32)];
+        assert!(scheme.is_close(&a, &b));
+        assert!(scheme.expand(&b).contains(&HammingBin {
+            chunk: 1,
+            bits: [0b1011_0000],
+        }));
+    }
+
+    #[test]
+    fn test_bin_to_key_is_injective() {
+        let a = HammingBin {
+            chunk: 0,
+            bits: [1u64, 0u64],
+        };
+        let b = HammingBin {
+            chunk: 1,
+            bits: [1u64, 0u64],
+        };
+        assert_ne!(a.to_key::<18>().0, b.to_key::<18>().0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_too_many_chunks() {
+        Hamming::<1>::new(8, 8);
+    }
+
+    /// A string of `bits` bits and the same string with up to delta bits flipped.
+    fn flipped_pair<const W: usize>(
+        rng: &mut ChaCha20Rng,
+        bits: usize,
+        delta: usize,
+    ) -> (BitString<W>, BitString<W>) {
+        let x: BitString<W> = and(&std::array::from_fn(|_| rng.random()), &mask(0..bits));
+        let mut y = x;
+        for _ in 0..rng.random_range(0..=delta) {
+            let bit = rng.random_range(0..bits);
+            y[bit / 64] ^= 1 << (bit % 64);
+        }
+        (x, y)
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        for (bits, delta) in [(32, 3), (64, 7)] {
+            let scheme = Hamming::<1>::new(bits, delta);
+            assert_conditional_overlap(&scheme, |rng| flipped_pair(rng, bits, delta));
+        }
+        let scheme = Hamming::<2>::new(100, 10);
+        assert_conditional_overlap(&scheme, |rng| flipped_pair(rng, 100, 10));
+        let scheme = Hamming::<4>::new(256, 24);
+        assert_conditional_overlap(&scheme, |rng| flipped_pair(rng, 256, 24));
+    }
+}
@@ -0,0 +1,116 @@
+//! Index from the bins of a party to the items that have the bin in their bin set.
+//! The index is built in a single pass over the items, so looking up the items of
+//! a bin does not require scanning the whole item list like
+//! [`BinningScheme::invert`] does.
+
+use super::{BinningScheme, Party};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Maps each bin of a party to the items of that party in the bin.
+#[derive(Debug, Clone)]
+pub struct BinIndex<B, P> {
+    bins: HashMap<B, Vec<P>>,
+}
+
+impl<B: Clone + Eq + Hash, P: Clone + Eq + Hash> BinIndex<B, P> {
+    /// Computes the bins of every item with "H_1" (Alice) or "H_2" (Bob) and records
+    /// the item under each of its bins. Duplicate items are only recorded once.
+    pub fn new<S>(scheme: &S, party: Party, points: &[P]) -> Self
+    where
+        S: BinningScheme<Bin = B, Point = P> + ?Sized,
+    {
+        let mut bins: HashMap<B, Vec<P>> = HashMap::new();
+        let mut seen = HashSet::new();
+
+        for point in points {
+            if !seen.insert(point) {
+                continue;
+            }
+            for bin in scheme.bins(party, point) {
+                bins.entry(bin).or_default().push(point.clone());
+            }
+        }
+
+        Self { bins }
+    }
+
+    /// The items in the given bin, empty if no item has the bin.
+    pub fn get(&self, bin: &B) -> &[P] {
+        self.bins.get(bin).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Iterates over all non-empty bins and their items.
+    pub fn iter(&self) -> impl Iterator<Item = (&B, &[P])> {
+        self.bins.iter().map(|(bin, items)| (bin, items.as_slice()))
+    }
+
+    /// Iterates over all non-empty bins.
+    pub fn bins(&self) -> impl Iterator<Item = &B> {
+        self.bins.keys()
+    }
+
+    /// The number of non-empty bins.
+    pub fn len(&self) -> usize {
+        self.bins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::l1::L1;
+    use crate::hash::split::Split;
+    use crate::hash::{Bin, LInfinity};
+
+    fn sorted<T: Ord + Clone>(items: &[T]) -> Vec<T> {
+        let mut items = items.to_vec();
+        items.sort();
+        items
+    }
+
+    fn assert_matches_invert<S>(scheme: &S, points: &[S::Point])
+    where
+        S: BinningScheme,
+        S::Point: Ord,
+    {
+        for party in [Party::Alice, Party::Bob] {
+            let index = BinIndex::new(scheme, party, points);
+            let all_bins: HashSet<_> = points.iter().flat_map(|p| scheme.bins(party, p)).collect();
+            assert_eq!(index.len(), all_bins.len());
+
+            for bin in &all_bins {
+                assert_eq!(
+                    sorted(index.get(bin)),
+                    sorted(&scheme.invert(party, bin, points))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_matches_invert() {
+        let points = vec![
+            [8u64, 20u64],
+            [9u64, 23u64],
+            [12u64, 20u64],
+            [2u64, 20u64],
+            [8u64, 20u64],
+        ];
+        assert_matches_invert(&LInfinity::<2>::new(2), &points);
+        assert_matches_invert(&L1::<2>::new(3), &points);
+        assert_matches_invert(&Split::<2>::new(2, 1), &points);
+    }
+
+    #[test]
+    fn test_get_unknown_bin() {
+        let index = BinIndex::new(&LInfinity::<1>::new(2), Party::Bob, &[[8u64]]);
+        assert_eq!(index.get(&Bin([2])), &[[8u64]]);
+        assert!(index.get(&Bin([3])).is_empty());
+        assert_eq!(index.bins().count(), 1);
+    }
+}
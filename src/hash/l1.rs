@@ -0,0 +1,181 @@
+//! Conditionally-overlapping hash functions for the l_1 (Manhattan) metric.
+//! The grid is the same as for the d_infinity metric (cells of side length 2 * delta),
+//! but H_1 only returns the cells that intersect the l_1 ball of radius delta around
+//! a point instead of the whole bounding box.
+
+use super::grid::Grid;
+use super::{Bin, BinningScheme, Party, Point};
+use std::collections::HashSet;
+
+/// "H_2" and its inversion: a point is assigned to the grid cell it lies in, which
+/// is the same as for the d_infinity metric.
+pub use super::{create_bin, invert_bin};
+
+/// "H_1": This computes all bins for a given set of points using the l_1 metric.
+/// For each point, only the bins that contain at least one position within
+/// l_1 distance delta of the point are returned.
+pub fn create_bins<const D: usize>(points: &[Point<D>], delta: u64) -> HashSet<Bin<D>> {
+    let scheme = L1::new(delta);
+    points
+        .iter()
+        .flat_map(|point| scheme.expand(point))
+        .collect()
+}
+
+/// Walks the grid dimension by dimension. In each dimension, only the cells whose
+/// distance to the point fits into the remaining budget of the ball are visited,
+/// and the budget for the following dimensions shrinks by that distance.
+fn collect_bins<const D: usize>(
+    grid: &Grid<D>,
+    point: &Point<D>,
+    dim: usize,
+    budget: u64,
+    bin: &mut Bin<D>,
+    bins: &mut HashSet<Bin<D>>,
+) {
+    if dim == D {
+        bins.insert(*bin);
+        return;
+    }
+
+    for cell in grid.cell_range(dim, point[dim], budget) {
+        let used = grid.cell_distance(dim, point[dim], cell);
+        bin.0[dim] = cell;
+        collect_bins(grid, point, dim + 1, budget - used, bin, bins);
+    }
+}
+
+/// l_1 distance between two points: the sum of the coordinate differences.
+/// Saturates at `u64::MAX` instead of overflowing.
+pub fn distance<const D: usize>(a: &Point<D>, b: &Point<D>) -> u64 {
+    a.iter()
+        .zip(b)
+        .fold(0u64, |sum, (a, b)| sum.saturating_add(a.abs_diff(*b)))
+}
+
+/// Final check of the protocol for the l_1 metric: whether the two points are
+/// at most delta apart.
+pub fn is_close<const D: usize>(a: &Point<D>, b: &Point<D>, delta: u64) -> bool {
+    distance(a, b) <= delta
+}
+
+/// Asymmetric binning for the l_1 metric: Alice expands her points into all cells
+/// that intersect the l_1 ball of radius delta ("H_1"), Bob uses the cell of his
+/// points ("H_2").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1<const D: usize> {
+    grid: Grid<D>,
+}
+
+impl<const D: usize> L1<D> {
+    /// Panics if delta is 0.
+    pub fn new(delta: u64) -> Self {
+        Self::with_grid(Grid::new(delta))
+    }
+
+    pub fn with_grid(grid: Grid<D>) -> Self {
+        Self { grid }
+    }
+}
+
+impl<const D: usize> BinningScheme for L1<D> {
+    type Point = Point<D>;
+    type Bin = Bin<D>;
+
+    fn expand(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let mut bins = HashSet::new();
+        let mut bin = Bin([0u64; D]);
+        collect_bins(&self.grid, point, 0, self.grid.delta(), &mut bin, &mut bins);
+        bins.into_iter().collect()
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        vec![self.grid.cell(point)]
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        let distance = self
+            .grid
+            .distances(a, b)
+            .iter()
+            .fold(0u64, |sum, &d| sum.saturating_add(d));
+        distance <= self.grid.delta()
+    }
+
+    /// Moving into a neighbouring cell costs at least 1 per dimension, so the ball
+    /// leaves the cell of the point in at most delta dimensions.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        match party {
+            Party::Alice => self.grid.max_cells_in_ball(self.grid.delta() as u128),
+            Party::Bob => Some(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::assert_conditional_overlap;
+    use super::*;
+    use rand::Rng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_create_bins_skips_corners() {
+        let delta = 2;
+        let bins = create_bins(&[[2u64, 2u64]], delta);
+        // The box [0,4]^2 touches the cells {0,1}^2, but the cell (1,1) starts at
+        // (4,4) which is l_1 distance 4 away.
+        let expected_bins: HashSet<Bin<2>> = vec![Bin([0, 0]), Bin([1, 0]), Bin([0, 1])]
+            .into_iter()
+            .collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_create_bins_subset_of_box() {
+        let points = vec![[20u64, 33u64, 7u64], [100u64, 3u64, 50u64]];
+        let delta = 5;
+        let bins = create_bins(&points, delta);
+        let box_bins = super::super::create_bins(&points, delta);
+        assert!(bins.is_subset(&box_bins));
+        assert!(bins.len() < box_bins.len());
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(&[3u64, 10u64], &[5u64, 4u64]), 8);
+        assert!(is_close(&[3u64, 10u64], &[4u64, 11u64], 2));
+        assert!(!is_close(&[3u64, 10u64], &[5u64, 11u64], 2));
+        assert_eq!(distance(&[0u64, 0u64], &[u64::MAX, u64::MAX]), u64::MAX);
+    }
+
+    /// A point and a point at most delta away from it in l_1 distance.
+    fn ball_pair<const D: usize>(rng: &mut ChaCha20Rng, delta: u64) -> (Point<D>, Point<D>) {
+        let x: Point<D> = std::array::from_fn(|_| rng.random_range(delta..1000));
+        let mut budget = rng.random_range(0..=delta);
+        let y = std::array::from_fn(|dim| {
+            let step = rng.random_range(0..=budget);
+            budget -= step;
+            if rng.random_bool(0.5) {
+                x[dim] + step
+            } else {
+                x[dim] - step
+            }
+        });
+        (x, y)
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        assert_conditional_overlap(&L1::<1>::new(3), |rng| ball_pair(rng, 3));
+        assert_conditional_overlap(&L1::<2>::new(5), |rng| ball_pair(rng, 5));
+        assert_conditional_overlap(&L1::<3>::new(4), |rng| ball_pair(rng, 4));
+        assert_conditional_overlap(&L1::<8>::new(10), |rng| ball_pair(rng, 10));
+    }
+
+    #[test]
+    fn test_conditional_overlap_shifted_grid() {
+        let scheme = L1::with_grid(Grid::<3>::shifted(6, [9u8; 32]));
+        assert_conditional_overlap(&scheme, |rng| ball_pair(rng, 6));
+    }
+}
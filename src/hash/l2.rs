@@ -0,0 +1,330 @@
+//! Conditionally-overlapping hash functions for the l_2 (Euclidean) metric.
+//! The grid is the same as for the d_infinity metric (cells of side length 2 * delta),
+//! but H_1 only returns the cells that intersect the l_2 ball of radius delta around
+//! a point. All distances are compared squared and in integers, so no rounding
+//! or overflow can occur.
+
+use super::grid::{Grid, Rotation, Seed};
+use super::{Bin, BinningScheme, Party, Point};
+use std::collections::HashSet;
+
+/// "H_2" and its inversion, the same as for the l_1 metric.
+pub use super::{create_bin, invert_bin};
+
+/// "H_1": This computes all bins for a given set of points using the l_2 metric.
+/// For each point, only the bins that contain at least one position within
+/// l_2 distance delta of the point are returned.
+pub fn create_bins<const D: usize>(points: &[Point<D>], delta: u64) -> HashSet<Bin<D>> {
+    let scheme = L2::new(delta);
+    points
+        .iter()
+        .flat_map(|point| scheme.expand(point))
+        .collect()
+}
+
+/// Walks the grid dimension by dimension. The budget is the remaining squared radius
+/// of the ball. In each dimension, only the cells within the square root of the budget
+/// are visited, and the squared distance to the cell is subtracted from the budget for
+/// the following dimensions.
+fn collect_bins<const D: usize>(
+    grid: &Grid<D>,
+    point: &Point<D>,
+    dim: usize,
+    budget: u128,
+    bin: &mut Bin<D>,
+    bins: &mut HashSet<Bin<D>>,
+) {
+    if dim == D {
+        bins.insert(*bin);
+        return;
+    }
+
+    // budget <= delta^2, so the root always fits into a u64.
+    let radius = budget.isqrt() as u64;
+    for cell in grid.cell_range(dim, point[dim], radius) {
+        let used = grid.cell_distance(dim, point[dim], cell) as u128;
+        bin.0[dim] = cell;
+        collect_bins(grid, point, dim + 1, budget - used * used, bin, bins);
+    }
+}
+
+/// Squared l_2 distance between two points. Each squared coordinate difference fits
+/// into a u128, the sum saturates at `u128::MAX`, which is larger than any squared u64.
+pub fn squared_distance<const D: usize>(a: &Point<D>, b: &Point<D>) -> u128 {
+    a.iter().zip(b).fold(0u128, |sum, (a, b)| {
+        let diff = a.abs_diff(*b) as u128;
+        sum.saturating_add(diff * diff)
+    })
+}
+
+/// Final check of the protocol for the l_2 metric: whether the two points are
+/// at most delta apart.
+pub fn is_close<const D: usize>(a: &Point<D>, b: &Point<D>, delta: u64) -> bool {
+    squared_distance(a, b) <= delta as u128 * delta as u128
+}
+
+/// Asymmetric binning for the l_2 metric: Alice expands her points into all cells
+/// that intersect the l_2 ball of radius delta ("H_1"), Bob uses the cell of his
+/// points ("H_2").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L2<const D: usize> {
+    grid: Grid<D>,
+}
+
+impl<const D: usize> L2<D> {
+    /// Panics if delta is 0.
+    pub fn new(delta: u64) -> Self {
+        Self::with_grid(Grid::new(delta))
+    }
+
+    pub fn with_grid(grid: Grid<D>) -> Self {
+        Self { grid }
+    }
+}
+
+impl<const D: usize> BinningScheme for L2<D> {
+    type Point = Point<D>;
+    type Bin = Bin<D>;
+
+    fn expand(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let mut bins = HashSet::new();
+        let mut bin = Bin([0u64; D]);
+        let delta = self.grid.delta() as u128;
+        collect_bins(&self.grid, point, 0, delta * delta, &mut bin, &mut bins);
+        bins.into_iter().collect()
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        vec![self.grid.cell(point)]
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        let delta = self.grid.delta() as u128;
+        let distance = self
+            .grid
+            .distances(a, b)
+            .iter()
+            .fold(0u128, |sum, &d| sum.saturating_add(d as u128 * d as u128));
+        distance <= delta * delta
+    }
+
+    /// Moving into a neighbouring cell costs at least 1 of the squared radius per
+    /// dimension, so the ball leaves the cell of the point in at most delta^2
+    /// dimensions.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        let delta = self.grid.delta() as u128;
+        match party {
+            Party::Alice => self.grid.max_cells_in_ball(delta * delta),
+            Party::Bob => Some(1),
+        }
+    }
+}
+
+/// Asymmetric binning for the l_2 metric on a randomly rotated and shifted grid.
+/// The rotation preserves l_2 distances, so the same bins as for [`L2`] are computed
+/// on the rotated positions of the points. The rotation and the shift are derived from
+/// a seed that both parties share, so both parties get the same grid.
+///
+/// The rotated positions are real numbers, thus the cells are computed in `f64`.
+/// Coordinates should stay below 2^52 so that they are exactly representable.
+/// Alice's ball is enlarged by a tiny slack to cover rounding errors, which may add
+/// a bin but never loses one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotatedL2<const D: usize> {
+    delta: u64,
+    rotation: Rotation<D>,
+    offset: [f64; D],
+}
+
+impl<const D: usize> RotatedL2<D> {
+    /// Panics if delta is 0.
+    pub fn new(delta: u64, seed: Seed) -> Self {
+        Self {
+            delta,
+            rotation: Rotation::from_seed(seed),
+            offset: Grid::<D>::shifted(delta, seed).offset().map(|o| o as f64),
+        }
+    }
+
+    fn side(&self) -> f64 {
+        2.0 * self.delta as f64
+    }
+
+    /// The rotated and shifted position of the point.
+    fn position(&self, point: &Point<D>) -> [f64; D] {
+        let rotated = self.rotation.apply(&point.map(|v| v as f64));
+        std::array::from_fn(|dim| rotated[dim] + self.offset[dim])
+    }
+
+    /// Index of the cell of a coordinate. Rotated coordinates can be negative, the
+    /// index is stored as two's complement.
+    fn cell_of(&self, val: f64) -> u64 {
+        (val / self.side()).floor() as i64 as u64
+    }
+
+    fn collect_bins(
+        &self,
+        position: &[f64; D],
+        dim: usize,
+        budget: f64,
+        bin: &mut Bin<D>,
+        bins: &mut HashSet<Bin<D>>,
+    ) {
+        if dim == D {
+            bins.insert(*bin);
+            return;
+        }
+
+        let val = position[dim];
+        let radius = budget.sqrt();
+        let first = (val - radius) / self.side();
+        let last = (val + radius) / self.side();
+        for cell in (first.floor() as i64)..=(last.floor() as i64) {
+            let lower = cell as f64 * self.side();
+            let used = (lower - val).max(val - (lower + self.side())).max(0.0);
+            bin.0[dim] = cell as u64;
+            self.collect_bins(position, dim + 1, budget - used * used, bin, bins);
+        }
+    }
+}
+
+impl<const D: usize> BinningScheme for RotatedL2<D> {
+    type Point = Point<D>;
+    type Bin = Bin<D>;
+
+    fn expand(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let position = self.position(point);
+        let scale = position
+            .iter()
+            .fold(self.delta as f64, |m, v| m.max(v.abs()));
+        let radius = self.delta as f64 + scale * 1e-9;
+
+        let mut bins = HashSet::new();
+        let mut bin = Bin([0u64; D]);
+        self.collect_bins(&position, 0, radius * radius, &mut bin, &mut bins);
+        bins.into_iter().collect()
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let position = self.position(point);
+        vec![Bin(position.map(|val| self.cell_of(val)))]
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        is_close(a, b, self.delta)
+    }
+
+    /// The slack makes the ball slightly wider than a cell, so it can touch three
+    /// cells per dimension.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        match party {
+            Party::Alice => 3usize.checked_pow(D as u32),
+            Party::Bob => Some(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::grid::Domain;
+    use super::super::testing::{assert_conditional_overlap, box_pair};
+    use super::*;
+
+    #[test]
+    fn test_create_bins_skips_corners() {
+        let delta = 5;
+        let bins = create_bins(&[[7u64, 7u64]], delta);
+        // The box [2,12]^2 touches the cells {0,1}^2. The cell (1,1) starts at (10,10)
+        // which is at squared distance 18 <= 25, the cells (0,0), (1,0), (0,1) contain
+        // the point or are at squared distance 9.
+        assert_eq!(bins.len(), 4);
+
+        let bins = create_bins(&[[6u64, 6u64]], delta);
+        // (1,1) is now at squared distance 32 > 25.
+        let expected_bins: HashSet<Bin<2>> = vec![Bin([0, 0]), Bin([1, 0]), Bin([0, 1])]
+            .into_iter()
+            .collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_create_bins_between_l1_and_box() {
+        let points = vec![[20u64, 33u64, 7u64], [100u64, 3u64, 56u64]];
+        let delta = 5;
+        let bins = create_bins(&points, delta);
+        let l1_bins = super::super::l1::create_bins(&points, delta);
+        let box_bins = super::super::create_bins(&points, delta);
+        assert!(l1_bins.is_subset(&bins));
+        assert!(bins.is_subset(&box_bins));
+    }
+
+    #[test]
+    fn test_squared_distance() {
+        assert_eq!(squared_distance(&[3u64, 10u64], &[6u64, 6u64]), 25);
+        assert!(is_close(&[3u64, 10u64], &[6u64, 6u64], 5));
+        assert!(!is_close(&[3u64, 10u64], &[6u64, 5u64], 5));
+    }
+
+    #[test]
+    fn test_squared_distance_does_not_overflow() {
+        let max = u64::MAX as u128;
+        assert_eq!(squared_distance(&[0u64], &[u64::MAX]), max * max);
+        assert_eq!(
+            squared_distance(&[0u64, 0u64], &[u64::MAX, u64::MAX]),
+            u128::MAX
+        );
+        assert!(!is_close(&[0u64, 0u64], &[u64::MAX, u64::MAX], u64::MAX));
+        assert!(is_close(&[0u64, 0u64], &[u64::MAX, 0u64], u64::MAX));
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        assert_conditional_overlap(&L2::<1>::new(3), |rng| box_pair(rng, 3));
+        assert_conditional_overlap(&L2::<2>::new(5), |rng| box_pair(rng, 5));
+        assert_conditional_overlap(&L2::<3>::new(4), |rng| box_pair(rng, 4));
+        assert_conditional_overlap(&L2::<8>::new(10), |rng| box_pair(rng, 10));
+    }
+
+    #[test]
+    fn test_conditional_overlap_shifted_grid() {
+        let seed = [3u8; 32];
+        let scheme = L2::with_grid(Grid::<1>::shifted(3, seed));
+        assert_conditional_overlap(&scheme, |rng| box_pair(rng, 3));
+        let scheme = L2::with_grid(Grid::<2>::shifted(5, seed));
+        assert_conditional_overlap(&scheme, |rng| box_pair(rng, 5));
+        let scheme = L2::with_grid(Grid::<8>::shifted(10, seed));
+        assert_conditional_overlap(&scheme, |rng| box_pair(rng, 10));
+    }
+
+    #[test]
+    fn test_conditional_overlap_cyclic_domain() {
+        let grid = Grid::<2>::shifted(5, [3u8; 32]).with_domain(Domain::Cyclic(360));
+        assert_conditional_overlap(&L2::with_grid(grid), |rng| box_pair(rng, 5));
+        let grid = Grid::<3>::new(4).with_domain(Domain::Cyclic(30));
+        assert_conditional_overlap(&L2::with_grid(grid), |rng| box_pair(rng, 4));
+    }
+
+    #[test]
+    fn test_conditional_overlap_rotated() {
+        let seed = [3u8; 32];
+        assert_conditional_overlap(&RotatedL2::<1>::new(3, seed), |rng| box_pair(rng, 3));
+        assert_conditional_overlap(&RotatedL2::<2>::new(5, seed), |rng| box_pair(rng, 5));
+        assert_conditional_overlap(&RotatedL2::<3>::new(4, seed), |rng| box_pair(rng, 4));
+        assert_conditional_overlap(&RotatedL2::<8>::new(10, seed), |rng| box_pair(rng, 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "delta > 0")]
+    fn test_rotated_zero_delta() {
+        RotatedL2::<2>::new(0, [1u8; 32]);
+    }
+
+    #[test]
+    fn test_rotated_grid_depends_on_seed() {
+        let a = RotatedL2::<2>::new(5, [1u8; 32]);
+        let b = RotatedL2::<2>::new(5, [2u8; 32]);
+        assert_eq!(a, RotatedL2::<2>::new(5, [1u8; 32]));
+        let points: Vec<Point<2>> = (0..20).map(|i| [50 * i, 1000 - 30 * i]).collect();
+        assert!(points.iter().any(|p| a.assign(p) != b.assign(p)));
+    }
+}
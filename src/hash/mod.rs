@@ -0,0 +1,597 @@
+use crate::okvs::near_optimal::okvs::OkvsKey;
+use grid::Grid;
+use index::BinIndex;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+pub mod angular;
+pub mod encoding;
+pub mod geo;
+pub mod grid;
+pub mod hamming;
+pub mod index;
+pub mod l1;
+pub mod l2;
+pub mod split;
+
+/// The two parties of the protocol. Alice expands her items into bins with "H_1" and
+/// encodes the first OKVS, Bob answers for the bins of his items from "H_2".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Party {
+    Alice,
+    Bob,
+}
+
+/// Identifiers of bins that can be used as keys of an OKVS.
+pub trait BinKey {
+    /// The number of bytes of the encoded bin.
+    const SIZE: usize;
+
+    /// Encodes the bin injectively into the first `SIZE` bytes of a key of N bytes,
+    /// the remaining bytes are zero. Panics if the bin does not fit into N bytes.
+    fn to_key<const N: usize>(&self) -> OkvsKey<N>;
+}
+
+/// A pair of conditionally-overlapping hash functions (H_1, H_2) for some metric
+/// and threshold, together with the final distance check of that metric.
+/// For two points `a` and `b` with `is_close(a, b)`, the bins `expand(a)` and
+/// `assign(b)` have at least one bin in common.
+pub trait BinningScheme {
+    /// The items of the parties.
+    type Point: Clone + Eq + Hash + Debug;
+    /// The identifier of a bin.
+    type Bin: Clone + Eq + Hash + Debug + BinKey;
+
+    /// "H_1": The bins of Alice for a single point.
+    fn expand(&self, point: &Self::Point) -> Vec<Self::Bin>;
+
+    /// "H_2": The bins of Bob for a single point.
+    fn assign(&self, point: &Self::Point) -> Vec<Self::Bin>;
+
+    /// Final check of the protocol: whether the two points are within the threshold.
+    fn is_close(&self, a: &Self::Point, b: &Self::Point) -> bool;
+
+    /// The largest number of bins the given party can get for a single point, which
+    /// only depends on the threshold and the dimension. The protocol sizes the OKVS
+    /// from it, so the size does not reveal anything about the points.
+    /// Returns `None` if the number does not fit into a `usize`.
+    fn max_bins(&self, party: Party) -> Option<usize>;
+
+    /// The bins of the given party for a single point.
+    fn bins(&self, party: Party, point: &Self::Point) -> Vec<Self::Bin> {
+        match party {
+            Party::Alice => self.expand(point),
+            Party::Bob => self.assign(point),
+        }
+    }
+
+    /// "H^(-1)": Computes the points of the given party that have the bin in their bins.
+    /// This scans all points, use [`BinningScheme::index`] to look up many bins.
+    fn invert(&self, party: Party, bin: &Self::Bin, points: &[Self::Point]) -> Vec<Self::Point> {
+        let mut items = HashSet::new();
+
+        for v in points {
+            if self.bins(party, v).contains(bin) {
+                items.insert(v.clone());
+            }
+        }
+
+        items.into_iter().collect()
+    }
+
+    /// Computes the bins of all points of the given party in one pass and indexes the
+    /// points by bin.
+    fn index(&self, party: Party, points: &[Self::Point]) -> BinIndex<Self::Bin, Self::Point> {
+        BinIndex::new(self, party, points)
+    }
+}
+
+/// A point in Z^d, given by its d coordinates.
+pub type Point<const D: usize> = [u64; D];
+
+/// A cell of the grid over Z^d with side length 2 * delta.
+/// The bin holds the index of the cell in every dimension, thus two different
+/// cells never share the same bin identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bin<const D: usize>(pub [u64; D]);
+
+impl<const D: usize> BinKey for Bin<D> {
+    const SIZE: usize = 8 * D;
+
+    /// Encodes the bin as an OKVS key. The cell indices are written in little endian
+    /// one after another and the remaining bytes are zero. As such, the encoding is
+    /// injective as long as the key has room for all `8 * D` bytes.
+    fn to_key<const N: usize>(&self) -> OkvsKey<N> {
+        assert!(
+            8 * D <= N,
+            "a bin of dimension {} needs a key of at least {} bytes, got {}",
+            D,
+            8 * D,
+            N
+        );
+
+        let mut key = [0u8; N];
+        for (bytes, cell) in key.chunks_exact_mut(8).zip(self.0) {
+            bytes.copy_from_slice(&cell.to_le_bytes());
+        }
+        OkvsKey(key)
+    }
+}
+
+/// Computes all combinations of the given cell indices per dimension.
+pub(crate) fn cartesian<const D: usize>(cells: &[Vec<u64>; D]) -> Vec<Bin<D>> {
+    let mut bins = vec![Bin([0u64; D])];
+
+    for (dim, dim_cells) in cells.iter().enumerate() {
+        bins = bins
+            .into_iter()
+            .flat_map(|bin| {
+                dim_cells.iter().map(move |&cell| {
+                    let mut bin = bin;
+                    bin.0[dim] = cell;
+                    bin
+                })
+            })
+            .collect();
+    }
+
+    bins
+}
+
+/// "H_1": This computes all bins for a given set of points.
+/// The bins are calculated using the d_infinity metric.
+/// Also, all bins are returned that are within the distance of each point.
+/// So, for all points, calculate all bins such that point - delta and point + delta
+/// are included in every dimension. The cost per point is the number of its bins
+/// (at most 2^d), independent of delta.
+pub fn create_bins<const D: usize>(points: &[Point<D>], delta: u64) -> HashSet<Bin<D>> {
+    let grid = Grid::new(delta);
+    points.iter().flat_map(|v| grid.cells_around(v)).collect()
+}
+
+/// "H_2": This computes the bin for a given point.
+/// This implementation uses the d_infinity metric. As such,
+/// the bin is the floor of each coordinate divided by 2 * delta.
+pub fn create_bin<const D: usize>(point: &Point<D>, delta: u64) -> Bin<D> {
+    Grid::new(delta).cell(point)
+}
+
+/// "H_1^(-1)": Kind of inversion of H_1. This computes a set of items from a bin.
+/// invert_bin(bin, points, delta) returns a list of points that would be in the bin.
+pub fn invert_bin<const D: usize>(bin: Bin<D>, points: &[Point<D>], delta: u64) -> Vec<Point<D>> {
+    let mut items = HashSet::new();
+
+    for v in points {
+        let bin_v = create_bin(v, delta);
+        if bin_v == bin {
+            items.insert(*v);
+        }
+    }
+
+    items.into_iter().collect()
+}
+
+/// Asymmetric binning for the d_infinity metric: Alice expands her points into all
+/// cells within distance delta ("H_1"), Bob uses the cell of his points ("H_2").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LInfinity<const D: usize> {
+    grid: Grid<D>,
+}
+
+impl<const D: usize> LInfinity<D> {
+    /// Binning on the grid anchored at 0. Panics if delta is 0.
+    pub fn new(delta: u64) -> Self {
+        Self::with_grid(Grid::new(delta))
+    }
+
+    /// Binning on the given grid, e.g., a grid shifted per session.
+    pub fn with_grid(grid: Grid<D>) -> Self {
+        Self { grid }
+    }
+}
+
+impl<const D: usize> BinningScheme for LInfinity<D> {
+    type Point = Point<D>;
+    type Bin = Bin<D>;
+
+    fn expand(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        self.grid.cells_around(point)
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        vec![self.grid.cell(point)]
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        let delta = self.grid.delta();
+        self.grid.distances(a, b).iter().all(|&d| d <= delta)
+    }
+
+    /// Alice gets the bounding box of the cells around her point, Bob a single cell.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        match party {
+            Party::Alice => self.grid.max_cells().checked_pow(D as u32),
+            Party::Bob => Some(1),
+        }
+    }
+
+    fn invert(&self, party: Party, bin: &Bin<D>, points: &[Point<D>]) -> Vec<Point<D>> {
+        let delta = self.grid.delta();
+        let in_bin = |v: &&Point<D>| match party {
+            Party::Alice => {
+                (0..D).all(|dim| self.grid.cell_distance(dim, v[dim], bin.0[dim]) <= delta)
+            }
+            Party::Bob => self.grid.cell(v) == *bin,
+        };
+
+        points
+            .iter()
+            .filter(in_bin)
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+/// d_infinity distance between two points: the largest difference of any coordinate.
+pub fn distance<const D: usize>(a: &Point<D>, b: &Point<D>) -> u64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Final check of the protocol for the d_infinity metric: whether the two points are
+/// at most delta apart.
+pub fn is_close<const D: usize>(a: &Point<D>, b: &Point<D>, delta: u64) -> bool {
+    distance(a, b) <= delta
+}
+
+/// Helpers shared by the tests of the binning schemes.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::{BinningScheme, Party, Point};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use std::collections::HashSet;
+
+    /// Draws pairs from `sample` until `pairs` of them are within the threshold and
+    /// returns the close pairs that share no bin. Every bin they do share must find
+    /// the point of each party when inverted.
+    pub(crate) fn missed_pairs<S: BinningScheme>(
+        scheme: &S,
+        pairs: usize,
+        mut sample: impl FnMut(&mut ChaCha20Rng) -> (S::Point, S::Point),
+    ) -> Vec<(S::Point, S::Point)> {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let mut checked = 0;
+        let mut missed = Vec::new();
+
+        while checked < pairs {
+            let (x, y) = sample(&mut rng);
+            if !scheme.is_close(&x, &y) {
+                continue;
+            }
+            checked += 1;
+
+            let bins_x: HashSet<_> = scheme.expand(&x).into_iter().collect();
+            let common: Vec<_> = scheme
+                .assign(&y)
+                .into_iter()
+                .filter(|bin| bins_x.contains(bin))
+                .collect();
+            for bin in &common {
+                assert_eq!(
+                    scheme.invert(Party::Alice, bin, std::slice::from_ref(&x)),
+                    vec![x.clone()]
+                );
+                assert_eq!(
+                    scheme.invert(Party::Bob, bin, std::slice::from_ref(&y)),
+                    vec![y.clone()]
+                );
+            }
+            if common.is_empty() {
+                missed.push((x, y));
+            }
+        }
+
+        missed
+    }
+
+    /// Asserts that 200 close pairs from `sample` share a bin. Pairs beyond the
+    /// threshold are skipped, so the sampler may overshoot.
+    pub(crate) fn assert_conditional_overlap<S: BinningScheme>(
+        scheme: &S,
+        sample: impl FnMut(&mut ChaCha20Rng) -> (S::Point, S::Point),
+    ) {
+        if let Some((x, y)) = missed_pairs(scheme, 200, sample).first() {
+            panic!("{:?} and {:?} do not share a bin", x, y);
+        }
+    }
+
+    /// A point with coordinates in `delta..1000` and a point at most delta away from
+    /// it in every coordinate.
+    pub(crate) fn box_pair<const D: usize>(
+        rng: &mut ChaCha20Rng,
+        delta: u64,
+    ) -> (Point<D>, Point<D>) {
+        let x: Point<D> = std::array::from_fn(|_| rng.random_range(delta..1000));
+        let y = std::array::from_fn(|dim| x[dim] + rng.random_range(0..=2 * delta) - delta);
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::grid::Domain;
+    use super::testing::{assert_conditional_overlap, box_pair};
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_create_bin() {
+        let value = [8u64];
+        let delta = 2;
+        let bin = create_bin(&value, delta);
+        // 8 / (2*2) = 8/4 = 2
+        assert_eq!(bin, Bin([2]));
+    }
+
+    #[test]
+    fn test_create_bin_multi_dimensional() {
+        let value = [8u64, 3u64, 17u64];
+        let delta = 2;
+        let bin = create_bin(&value, delta);
+        assert_eq!(bin, Bin([2, 0, 4]));
+    }
+
+    #[test]
+    fn test_create_bins() {
+        let points = vec![[8u64], [12u64]];
+        let delta = 2;
+        let bins = create_bins(&points, delta);
+        // For 8: (6..=10) gives bins: 6/4=1, 7/4=1, 8/4=2, 9/4=2, 10/4=2 -> {1,2}
+        // For 12: (10..=14) gives bins: 10/4=2, 11/4=2, 12/4=3, 13/4=3, 14/4=3 -> {2,3}
+        // Merged bins: {1,2,3}
+        let expected_bins: HashSet<Bin<1>> =
+            vec![Bin([1]), Bin([2]), Bin([3])].into_iter().collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_create_bins_large_delta() {
+        // Millisecond timestamps with a threshold of roughly 11 days.
+        let delta = 1_000_000_000;
+        let points = vec![[1_700_000_000_000u64, 3u64]];
+        let bins = create_bins(&points, delta);
+        // x: 1_699_000_000_000 / 2e9 = 849, 1_701_000_000_000 / 2e9 = 850, y: {0}
+        let expected_bins: HashSet<Bin<2>> =
+            vec![Bin([849, 0]), Bin([850, 0])].into_iter().collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_create_bins_near_max() {
+        let delta = 5;
+        let points = vec![[u64::MAX - 1]];
+        let bins = create_bins(&points, delta);
+        let expected_bins: HashSet<Bin<1>> = vec![Bin([(u64::MAX - 6) / 10]), Bin([u64::MAX / 10])]
+            .into_iter()
+            .collect();
+        assert_eq!(expected_bins, bins);
+        assert!(bins.contains(&create_bin(&[u64::MAX], delta)));
+    }
+
+    #[test]
+    fn test_create_bins_two_dimensional() {
+        let points = vec![[8u64, 5u64]];
+        let delta = 2;
+        let bins = create_bins(&points, delta);
+        // x: (6..=10) -> {1,2}, y: (3..=7) -> {0,1}
+        let expected_bins: HashSet<Bin<2>> =
+            vec![Bin([1, 0]), Bin([1, 1]), Bin([2, 0]), Bin([2, 1])]
+                .into_iter()
+                .collect();
+        assert_eq!(expected_bins, bins);
+    }
+
+    #[test]
+    fn test_points_in_same_bin() {
+        let value1 = [8u64, 20u64];
+        let value2 = [9u64, 23u64];
+        let delta = 2;
+        let bin1 = create_bin(&value1, delta);
+        let bin2 = create_bin(&value2, delta);
+        assert_eq!(bin1, bin2);
+    }
+
+    #[test]
+    fn test_invert_bin() {
+        let points = vec![[8u64, 20u64], [9u64, 23u64], [12u64, 20u64]];
+        let delta = 2;
+        let mut items = invert_bin(Bin([2, 5]), &points, delta);
+        items.sort();
+        assert_eq!(items, vec![[8, 20], [9, 23]]);
+    }
+
+    #[test]
+    fn test_scheme_invert() {
+        let scheme = LInfinity::<2>::new(2);
+        let points = vec![[8u64, 20u64], [9u64, 23u64], [12u64, 20u64], [2u64, 20u64]];
+        for bin in [Bin([2, 5]), Bin([1, 4]), Bin([3, 5])] {
+            for party in [Party::Alice, Party::Bob] {
+                let mut items = scheme.invert(party, &bin, &points);
+                items.sort();
+                let expected: Vec<_> = points
+                    .iter()
+                    .filter(|p| scheme.bins(party, p).contains(&bin))
+                    .copied()
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .into_iter()
+                    .collect();
+                assert_eq!(items, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        assert_conditional_overlap(&LInfinity::<1>::new(3), |rng| box_pair(rng, 3));
+        assert_conditional_overlap(&LInfinity::<2>::new(5), |rng| box_pair(rng, 5));
+        assert_conditional_overlap(&LInfinity::<3>::new(1), |rng| box_pair(rng, 1));
+        assert_conditional_overlap(&LInfinity::<8>::new(4), |rng| box_pair(rng, 4));
+    }
+
+    #[test]
+    fn test_conditional_overlap_shifted_grid() {
+        let scheme = LInfinity::with_grid(Grid::<3>::shifted(5, [9u8; 32]));
+        assert_conditional_overlap(&scheme, |rng| box_pair(rng, 5));
+    }
+
+    #[test]
+    fn test_conditional_overlap_cyclic_domain() {
+        let delta = 5;
+        let grid = Grid::<2>::shifted(delta, [9u8; 32]).with_domain(Domain::Cyclic(360));
+        let scheme = LInfinity::with_grid(grid);
+        assert_conditional_overlap(&scheme, |rng| {
+            let x: Point<2> = std::array::from_fn(|_| rng.random_range(0..360));
+            let y = std::array::from_fn(|dim| {
+                (x[dim] + 360 + rng.random_range(0..=2 * delta) - delta) % 360
+            });
+            (x, y)
+        });
+
+        assert!(scheme.is_close(&[359, 0], &[1, 358]));
+        assert!(!scheme.is_close(&[359, 0], &[5, 0]));
+    }
+
+    fn assert_max_bins<S: BinningScheme>(scheme: &S, points: &[S::Point], exact: bool) {
+        for party in [Party::Alice, Party::Bob] {
+            let most = points
+                .iter()
+                .map(|point| scheme.bins(party, point).len())
+                .max()
+                .unwrap();
+            let max_bins = scheme.max_bins(party).unwrap();
+            assert!(most <= max_bins, "{:?}: {}", party, most);
+            if exact {
+                assert_eq!(most, max_bins, "{:?}", party);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_bins() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let points: Vec<Point<3>> = (0..500)
+            .map(|_| std::array::from_fn(|_| rng.random_range(0..1000)))
+            .collect();
+        let seed = [5u8; 32];
+
+        assert_max_bins(&LInfinity::<3>::new(5), &points, true);
+        assert_max_bins(&l1::L1::<3>::new(5), &points, true);
+        assert_max_bins(&l2::L2::<3>::new(5), &points, true);
+        assert_max_bins(&split::Split::<3>::new(5, 1), &points, true);
+        assert_max_bins(&l2::RotatedL2::<3>::new(5, seed), &points, false);
+        let grid = Grid::<3>::shifted(5, seed).with_domain(Domain::Cyclic(105));
+        assert_max_bins(&LInfinity::with_grid(grid), &points, false);
+
+        let bits: Vec<[u64; 1]> = (0..100).map(|_| [rng.random()]).collect();
+        assert_max_bins(&hamming::Hamming::<1>::new(64, 5), &bits, true);
+        assert_max_bins(
+            &angular::Angular::<3>::new(0.2, 8, 0.01, seed),
+            &points,
+            true,
+        );
+
+        let encoder = geo::Geo::encoder();
+        let places: Vec<Point<2>> = (0..2000)
+            .map(|_| {
+                let latitude = rng.random_range(-90.0..=90.0);
+                encoder.encode(&[latitude, rng.random_range(-180.0..180.0)])
+            })
+            .collect();
+        assert_max_bins(&geo::Geo::new(100.0), &places, false);
+        assert_max_bins(&geo::Geo::new(500_000.0), &places, false);
+    }
+
+    #[test]
+    fn test_max_bins_beyond_delta() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let mut points: Vec<Point<8>> = (0..500)
+            .map(|_| std::array::from_fn(|_| rng.random_range(0..1000)))
+            .collect();
+        // next to a corner of the cells (0..=7)^8 and (0..=3)^8
+        points.push([7; 8]);
+        points.push([3; 8]);
+
+        // the sum of binomial(8, k) for k <= 4
+        assert_eq!(l1::L1::<8>::new(4).max_bins(Party::Alice), Some(163));
+        assert_max_bins(&l1::L1::<8>::new(4), &points, true);
+        assert_max_bins(&l2::L2::<8>::new(2), &points, true);
+        assert_eq!(l1::L1::<32>::new(2).max_bins(Party::Alice), Some(529));
+
+        // two neighbouring cells per dimension next to the shorter last cell
+        let grid = Grid::<8>::new(2).with_domain(Domain::Cyclic(10));
+        assert_eq!(
+            l1::L1::with_grid(grid).max_bins(Party::Alice),
+            Some(1 + 16 + 112)
+        );
+        let points: Vec<Point<8>> = (0..500)
+            .map(|_| std::array::from_fn(|_| rng.random_range(0..10)))
+            .collect();
+        assert_max_bins(&l1::L1::with_grid(grid), &points, false);
+        assert_max_bins(&l2::L2::with_grid(grid), &points, false);
+    }
+
+    #[test]
+    fn test_max_bins_overflow() {
+        assert_eq!(LInfinity::<64>::new(5).max_bins(Party::Alice), None);
+        assert_eq!(LInfinity::<64>::new(5).max_bins(Party::Bob), Some(1));
+        assert_eq!(l1::L1::<128>::new(64).max_bins(Party::Alice), None);
+        assert_eq!(l2::L2::<128>::new(8).max_bins(Party::Alice), None);
+        assert_eq!(
+            l2::RotatedL2::<41>::new(5, [5u8; 32]).max_bins(Party::Alice),
+            None
+        );
+        let scheme = split::Split::<128>::new(5, 64);
+        assert_eq!(scheme.max_bins(Party::Alice), None);
+        assert_eq!(scheme.max_bins(Party::Bob), None);
+        assert_eq!(
+            split::Split::<128>::new(5, 10).max_bins(Party::Alice),
+            Some(1024)
+        );
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(&[3u64, 10u64], &[5u64, 4u64]), 6);
+        assert!(is_close(&[3u64, 10u64], &[5u64, 12u64], 2));
+        assert!(!is_close(&[3u64, 10u64], &[5u64, 13u64], 2));
+    }
+
+    #[test]
+    fn test_bin_to_key_is_injective() {
+        let a = Bin([1u64, 0u64]).to_key::<16>();
+        let b = Bin([0u64, 1u64]).to_key::<16>();
+        assert_ne!(a.0, b.0);
+        assert_eq!(Bin([7u64]).to_key::<8>().0, 7u64.to_le_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "delta > 0")]
+    fn test_zero_delta() {
+        LInfinity::<1>::new(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_bin_to_key_too_small() {
+        Bin([1u64, 2u64]).to_key::<8>();
+    }
+}
@@ -0,0 +1,226 @@
+//! Split-dimensional binning for the d_infinity grid.
+//! Instead of one party expanding every dimension ("H_1") while the other party
+//! only computes its own cell ("H_2"), the expansion is split between the parties:
+//! "H_3" expands the first `s` dimensions, "H_4" the remaining `d - s` dimensions.
+//! For two points within distance delta, the cell that takes the first `s` indices
+//! from the second point and the remaining indices from the first point is in both
+//! bin sets. Choosing `s` close to `d / 2` balances the number of bins per party.
+
+use super::grid::Grid;
+use super::{cartesian, Bin, BinningScheme, Party, Point};
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// The dimensions the given party expands over when splitting at `s`: Alice the
+/// first `s` dimensions ("H_3"), Bob the remaining ones ("H_4").
+fn expanded(party: Party, s: usize, dimensions: usize) -> Range<usize> {
+    assert!(
+        s <= dimensions,
+        "cannot split at dimension {} of {}",
+        s,
+        dimensions
+    );
+
+    match party {
+        Party::Alice => 0..s,
+        Party::Bob => s..dimensions,
+    }
+}
+
+/// Split-dimensional binning for the d_infinity metric as a binning scheme:
+/// Alice's bins are given by "H_3" and Bob's bins by "H_4".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Split<const D: usize> {
+    grid: Grid<D>,
+    s: usize,
+}
+
+impl<const D: usize> Split<D> {
+    /// Panics if delta is 0 or `s` is larger than the dimension of the points.
+    pub fn new(delta: u64, s: usize) -> Self {
+        Self::with_grid(Grid::new(delta), s)
+    }
+
+    /// Panics if `s` is larger than the dimension of the points.
+    pub fn with_grid(grid: Grid<D>, s: usize) -> Self {
+        assert!(s <= D, "cannot split at dimension {} of {}", s, D);
+        Self { grid, s }
+    }
+}
+
+impl<const D: usize> BinningScheme for Split<D> {
+    type Point = Point<D>;
+    type Bin = Bin<D>;
+
+    fn expand(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let expanded = expanded(Party::Alice, self.s, D);
+        create_bins_in(&self.grid, &[*point], expanded)
+            .into_iter()
+            .collect()
+    }
+
+    fn assign(&self, point: &Point<D>) -> Vec<Bin<D>> {
+        let expanded = expanded(Party::Bob, self.s, D);
+        create_bins_in(&self.grid, &[*point], expanded)
+            .into_iter()
+            .collect()
+    }
+
+    fn is_close(&self, a: &Point<D>, b: &Point<D>) -> bool {
+        let delta = self.grid.delta();
+        self.grid.distances(a, b).iter().all(|&d| d <= delta)
+    }
+
+    /// Each party expands over its share of the dimensions.
+    fn max_bins(&self, party: Party) -> Option<usize> {
+        let expanded = expanded(party, self.s, D);
+        self.grid.max_cells().checked_pow(expanded.len() as u32)
+    }
+
+    fn invert(&self, party: Party, bin: &Bin<D>, points: &[Point<D>]) -> Vec<Point<D>> {
+        let expanded = expanded(party, self.s, D);
+        invert_bin_in(&self.grid, *bin, points, expanded)
+    }
+}
+
+/// "H_3": This computes all bins for a set of points, expanding the first `s`
+/// dimensions by delta and using the cell of the point in the remaining dimensions.
+pub fn create_bins_first<const D: usize>(
+    points: &[Point<D>],
+    delta: u64,
+    s: usize,
+) -> HashSet<Bin<D>> {
+    create_bins_in(&Grid::new(delta), points, expanded(Party::Alice, s, D))
+}
+
+/// "H_4": This computes all bins for a set of points, using the cell of the point in
+/// the first `s` dimensions and expanding the remaining dimensions by delta.
+pub fn create_bins_last<const D: usize>(
+    points: &[Point<D>],
+    delta: u64,
+    s: usize,
+) -> HashSet<Bin<D>> {
+    create_bins_in(&Grid::new(delta), points, expanded(Party::Bob, s, D))
+}
+
+fn create_bins_in<const D: usize>(
+    grid: &Grid<D>,
+    points: &[Point<D>],
+    expanded: Range<usize>,
+) -> HashSet<Bin<D>> {
+    let mut bins = HashSet::new();
+
+    for v in points {
+        let cells: [Vec<u64>; D] = std::array::from_fn(|dim| {
+            if expanded.contains(&dim) {
+                grid.cell_range(dim, v[dim], grid.delta())
+            } else {
+                vec![grid.cell_of(dim, v[dim])]
+            }
+        });
+        bins.extend(cartesian(&cells));
+    }
+
+    bins
+}
+
+fn invert_bin_in<const D: usize>(
+    grid: &Grid<D>,
+    bin: Bin<D>,
+    points: &[Point<D>],
+    expanded: Range<usize>,
+) -> Vec<Point<D>> {
+    let mut items = HashSet::new();
+
+    for v in points {
+        let covered = (0..D).all(|dim| {
+            if expanded.contains(&dim) {
+                grid.cell_distance(dim, v[dim], bin.0[dim]) <= grid.delta()
+            } else {
+                grid.cell_of(dim, v[dim]) == bin.0[dim]
+            }
+        });
+        if covered {
+            items.insert(*v);
+        }
+    }
+
+    items.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{assert_conditional_overlap, box_pair};
+    use super::*;
+
+    #[test]
+    fn test_create_bins_first_and_last() {
+        let delta = 2;
+        let points = vec![[8u64, 5u64, 13u64]];
+        // x: {1,2}, y: {0,1}, z: {2,3}; own cell: (2,1,3)
+        let first: HashSet<Bin<3>> = vec![Bin([1, 1, 3]), Bin([2, 1, 3])].into_iter().collect();
+        assert_eq!(create_bins_first(&points, delta, 1), first);
+
+        let last: HashSet<Bin<3>> = vec![
+            Bin([2, 0, 2]),
+            Bin([2, 0, 3]),
+            Bin([2, 1, 2]),
+            Bin([2, 1, 3]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(create_bins_last(&points, delta, 1), last);
+    }
+
+    #[test]
+    fn test_split_extremes_are_asymmetric() {
+        let delta = 3;
+        let points = vec![[8u64, 5u64], [40u64, 2u64]];
+        let h1 = super::super::create_bins(&points, delta);
+        let h2: HashSet<Bin<2>> = points
+            .iter()
+            .map(|p| super::super::create_bin(p, delta))
+            .collect();
+
+        assert_eq!(create_bins_first(&points, delta, 2), h1);
+        assert_eq!(create_bins_last(&points, delta, 2), h2);
+        assert_eq!(create_bins_first(&points, delta, 0), h2);
+        assert_eq!(create_bins_last(&points, delta, 0), h1);
+    }
+
+    #[test]
+    fn test_invert_bin() {
+        let delta = 2;
+        let points = vec![[8u64, 5u64], [12u64, 5u64], [8u64, 9u64]];
+        let scheme = Split::new(delta, 1);
+
+        let mut items = scheme.invert(Party::Alice, &Bin([2, 1]), &points);
+        items.sort();
+        assert_eq!(items, vec![[8, 5], [12, 5]]);
+
+        let mut items = scheme.invert(Party::Bob, &Bin([2, 1]), &points);
+        items.sort();
+        assert_eq!(items, vec![[8, 5], [8, 9]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_out_of_range() {
+        create_bins_last(&[[1u64, 2u64]], 2, 3);
+    }
+
+    #[test]
+    fn test_conditional_overlap() {
+        assert_conditional_overlap(&Split::<1>::new(3, 0), |rng| box_pair(rng, 3));
+        assert_conditional_overlap(&Split::<1>::new(3, 1), |rng| box_pair(rng, 3));
+        assert_conditional_overlap(&Split::<2>::new(5, 1), |rng| box_pair(rng, 5));
+        assert_conditional_overlap(&Split::<3>::new(1, 2), |rng| box_pair(rng, 1));
+        assert_conditional_overlap(&Split::<8>::new(4, 4), |rng| box_pair(rng, 4));
+    }
+
+    #[test]
+    fn test_conditional_overlap_shifted_grid() {
+        let scheme = Split::with_grid(Grid::<4>::shifted(4, [9u8; 32]), 2);
+        assert_conditional_overlap(&scheme, |rng| box_pair(rng, 4));
+    }
+}
@@ -1,10 +1,15 @@
-use ark_ff::{Field, PrimeField, Zero};
+use super::wire::error::{Error, Result};
+use super::wire::{Kind, Reader, Writer};
+use ark_ff::{BigInteger, Field, PrimeField, Zero};
 use ark_poly::univariate::DensePolynomial;
 use ark_poly::{DenseUVPolynomial, Polynomial};
 use ark_test_curves::bls12_381::Fr;
 use num_bigint::BigUint;
 use std::collections::HashSet;
 
+/// The size of a serialized field element in bytes.
+const ELEMENT_SIZE: usize = (Fr::MODULUS_BIT_SIZE as usize).div_ceil(64) * 8;
+
 pub struct LagrangePolynomialOKVS(DensePolynomial<Fr>);
 
 impl LagrangePolynomialOKVS {
@@ -20,9 +25,8 @@ impl LagrangePolynomialOKVS {
             let (x_i, y_i) = points[i];
             let mut l_i = DensePolynomial::from_coefficients_vec(vec![Fr::ONE]);
 
-            for j in 0..n {
+            for (j, &(x_j, _)) in points.iter().enumerate() {
                 if i != j {
-                    let (x_j, _) = points[j];
                     let denominator = x_i - x_j;
                     let denominator_inv = denominator.inverse().unwrap();
                     let term = DensePolynomial::from_coefficients_vec(vec![
@@ -44,13 +48,57 @@ impl LagrangePolynomialOKVS {
         let key = key.into();
         let p = Fr::from(key);
         let y = self.0.evaluate(&p);
+        // zero has no digits
         let y = BigUint::from(y.into_bigint()).to_u64_digits();
-        let y = y[0];
+        let y = y.first().copied().unwrap_or(0);
 
-        Point {
-            x: key,
-            y: y.into(),
+        Point { x: key, y }
+    }
+
+    /// Serializes the coefficients in the [wire format](super::wire).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(Kind::Lagrange);
+        writer.u64(self.0.coeffs.len() as u64);
+        writer.u32(ELEMENT_SIZE as u32);
+        for coefficient in &self.0.coeffs {
+            writer.bytes(&coefficient.into_bigint().to_bytes_le());
+        }
+        writer.finish()
+    }
+
+    /// The OKVS of bytes in the [wire format](super::wire). Fails if the header is
+    /// invalid, a coefficient is not a canonical element of the field, or the leading
+    /// coefficient is zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes, Kind::Lagrange)?;
+        let count = reader.len()?;
+        let size = reader.u32()? as usize;
+        if size != ELEMENT_SIZE {
+            return Err(Error::Parameters(format!(
+                "field elements have {} bytes, got {}",
+                ELEMENT_SIZE, size
+            )));
         }
+
+        let coeffs = reader
+            .items(count, size)?
+            .chunks_exact(size)
+            .enumerate()
+            .map(|(i, bytes)| {
+                let coefficient = Fr::from_le_bytes_mod_order(bytes);
+                (coefficient.into_bigint().to_bytes_le() == bytes)
+                    .then_some(coefficient)
+                    .ok_or(Error::Value(i))
+            })
+            .collect::<Result<Vec<Fr>>>()?;
+        reader.finish()?;
+
+        if coeffs.last().is_some_and(Zero::is_zero) {
+            return Err(Error::Parameters(
+                "the leading coefficient is zero".to_string(),
+            ));
+        }
+        Ok(Self(DensePolynomial { coeffs }))
     }
 }
 
@@ -92,6 +140,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::okvs::near_optimal::okvs::{Okvs, OkvsKey, OkvsValue, RbOkvs};
 
     #[test]
     fn test_encode() {
@@ -141,6 +190,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_wire_roundtrip() {
+        let data: HashSet<Point> = (1..=8u64).map(|x| Point::new(x, x * x + 3)).collect();
+        let okvs = LagrangePolynomialOKVS::encode(&data);
+        let decoded = LagrangePolynomialOKVS::from_bytes(&okvs.to_bytes()).unwrap();
+
+        assert_eq!(decoded.0, okvs.0);
+        for point in &data {
+            assert_eq!(decoded.decode(point.x), *point);
+        }
+    }
+
+    #[test]
+    fn test_wire_rejects_invalid_input() {
+        let data: HashSet<Point> = (1..=3u64).map(|x| Point::new(x, x)).collect();
+        let bytes = LagrangePolynomialOKVS::encode(&data).to_bytes();
+
+        // a coefficient equal to the modulus is not canonical
+        let mut modulus = bytes.clone();
+        modulus[18..18 + ELEMENT_SIZE].copy_from_slice(&Fr::MODULUS.to_bytes_le());
+        assert_eq!(
+            LagrangePolynomialOKVS::from_bytes(&modulus).err(),
+            Some(Error::Value(0))
+        );
+        assert!(matches!(
+            LagrangePolynomialOKVS::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::Truncated { .. })
+        ));
+
+        let encoding = RbOkvs::new(1)
+            .encode(vec![(
+                OkvsKey(1u64.to_le_bytes()),
+                OkvsValue(1u64.to_le_bytes()),
+            )])
+            .unwrap();
+        assert!(matches!(
+            LagrangePolynomialOKVS::from_bytes(&encoding.to_bytes()),
+            Err(Error::Kind { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_zero() {
+        let data: HashSet<Point> = (1..=3u64).map(|x| Point::new(x, 0u64)).collect();
+        let okvs = LagrangePolynomialOKVS::encode(&data);
+        assert_eq!(okvs.decode(2u64).y, 0);
+    }
+
     #[test]
     fn test_decode_invalid_key() {
         let data: HashSet<Point> = vec![
@@ -1,2 +1,3 @@
 pub mod lagrange;
 pub mod near_optimal;
+pub mod wire;
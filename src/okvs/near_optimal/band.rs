@@ -0,0 +1,327 @@
+//! The random band of a row of the RB-OKVS matrix. Bands of up to 64 and 128 bits use
+//! the native integers, wider bands an array of 64-bit words, so small configurations
+//! do not pay for wide arithmetic.
+//!
+//! The band of a key is a bit vector. Elimination works on [`Row`]s: over a field of
+//! characteristic 2, a row stays a bit vector, over other fields, its coefficients
+//! become arbitrary field elements (see [`FieldRow`](super::field::FieldRow)).
+
+use super::field::Scalar;
+use super::okvs::OkvsV;
+use std::fmt::Debug;
+
+/// A bit vector of `BITS` bits that holds the band of a row. Bit 0 is the first column
+/// of the band. Shifts drop the bits that are shifted out.
+pub trait Band: Copy + Eq + Debug {
+    const BITS: usize;
+
+    /// The band of the little-endian bytes, which must fit into `BITS` bits.
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    /// The index of the lowest set bit, `BITS` for the zero band.
+    fn trailing_zeros(&self) -> usize;
+
+    fn bit(&self, index: usize) -> bool;
+
+    /// The i-th 64-bit word, starting from the lowest bits.
+    fn word(&self, i: usize) -> u64;
+
+    fn xor(self, other: Self) -> Self;
+
+    fn shl(self, n: usize) -> Self;
+
+    fn shr(self, n: usize) -> Self;
+
+    /// The indices of the set bits in increasing order.
+    fn ones(&self) -> Ones<'_, Self> {
+        Ones {
+            band: self,
+            index: 0,
+            word: self.word(0),
+        }
+    }
+
+    /// The sum of the values at the set bits, i.e., the inner product of the band with
+    /// the values. Only needs the addition of the values, so it decodes any value.
+    fn inner_product<V: OkvsV>(&self, x: &[V]) -> V {
+        let mut result = V::default();
+        for i in self.ones() {
+            result.in_place_add(&x[i]);
+        }
+        result
+    }
+}
+
+/// The indices of the set bits of a band. Skips the zero bits a word at a time with
+/// `trailing_zeros`, so the cost is proportional to the number of set bits.
+pub struct Ones<'a, B: Band> {
+    band: &'a B,
+    /// The index of the current word.
+    index: usize,
+    /// The bits of the current word that are not yet returned.
+    word: u64,
+}
+
+impl<B: Band> Iterator for Ones<'_, B> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.index += 1;
+            if self.index == B::BITS / 64 {
+                return None;
+            }
+            self.word = self.band.word(self.index);
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(64 * self.index + bit)
+    }
+}
+
+/// A row of the band matrix during elimination, with coefficients in the field of the
+/// values. Index 0 is the first column of the band.
+pub trait Row: Clone {
+    type Scalar: Scalar;
+
+    /// The index of the first nonzero coefficient, `None` for the zero row.
+    fn leading(&self) -> Option<usize>;
+
+    fn coefficient(&self, index: usize) -> Self::Scalar;
+
+    /// Adds `factor` times the row `other` to this row, where the bands start at
+    /// `start_other` and `start_self` relative to the same column. `other` is shifted
+    /// onto this row, so no coefficients of this row leave the band.
+    fn add_scaled(
+        &mut self,
+        other: &Self,
+        factor: Self::Scalar,
+        start_other: usize,
+        start_self: usize,
+    );
+
+    /// The indices and coefficients of the nonzero coefficients, in increasing order.
+    fn nonzero(&self) -> impl Iterator<Item = (usize, Self::Scalar)>;
+}
+
+/// Over GF(2), the only nonzero factor is 1, so adding a row is a XOR.
+impl<B: Band> Row for B {
+    type Scalar = bool;
+
+    fn leading(&self) -> Option<usize> {
+        (!self.is_zero()).then(|| self.trailing_zeros())
+    }
+
+    fn coefficient(&self, index: usize) -> bool {
+        self.bit(index)
+    }
+
+    fn add_scaled(&mut self, other: &Self, factor: bool, start_other: usize, start_self: usize) {
+        if !factor {
+            return;
+        }
+        let shifted = match start_other.cmp(&start_self) {
+            std::cmp::Ordering::Equal => *other,
+            std::cmp::Ordering::Less => other.shl(start_self - start_other),
+            std::cmp::Ordering::Greater => other.shr(start_other - start_self),
+        };
+        *self = self.xor(shifted);
+    }
+
+    fn nonzero(&self) -> impl Iterator<Item = (usize, bool)> {
+        self.ones().map(|i| (i, true))
+    }
+}
+
+impl Band for u64 {
+    const BITS: usize = 64;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(buf)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn trailing_zeros(&self) -> usize {
+        u64::trailing_zeros(*self) as usize
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self >> index & 1 == 1
+    }
+
+    fn word(&self, _i: usize) -> u64 {
+        *self
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+
+    fn shl(self, n: usize) -> Self {
+        self.checked_shl(n as u32).unwrap_or(0)
+    }
+
+    fn shr(self, n: usize) -> Self {
+        self.checked_shr(n as u32).unwrap_or(0)
+    }
+}
+
+impl Band for u128 {
+    const BITS: usize = 128;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 16];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        u128::from_le_bytes(buf)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn trailing_zeros(&self) -> usize {
+        u128::trailing_zeros(*self) as usize
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self >> index & 1 == 1
+    }
+
+    fn word(&self, i: usize) -> u64 {
+        (self >> (64 * i)) as u64
+    }
+
+    fn xor(self, other: Self) -> Self {
+        self ^ other
+    }
+
+    fn shl(self, n: usize) -> Self {
+        self.checked_shl(n as u32).unwrap_or(0)
+    }
+
+    fn shr(self, n: usize) -> Self {
+        self.checked_shr(n as u32).unwrap_or(0)
+    }
+}
+
+/// A band of `64 * W` bits as little-endian words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Words<const W: usize>(pub [u64; W]);
+
+impl<const W: usize> Band for Words<W> {
+    const BITS: usize = 64 * W;
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut words = [0u64; W];
+        for (word, chunk) in words.iter_mut().zip(bytes.chunks(8)) {
+            *word = <u64 as Band>::from_le_bytes(chunk);
+        }
+        Self(words)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|&word| word == 0)
+    }
+
+    fn trailing_zeros(&self) -> usize {
+        self.0
+            .iter()
+            .position(|&word| word != 0)
+            .map_or(Self::BITS, |i| 64 * i + self.0[i].trailing_zeros() as usize)
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.0[index / 64].bit(index % 64)
+    }
+
+    fn word(&self, i: usize) -> u64 {
+        self.0[i]
+    }
+
+    fn xor(self, other: Self) -> Self {
+        Self(std::array::from_fn(|i| self.0[i] ^ other.0[i]))
+    }
+
+    fn shl(self, n: usize) -> Self {
+        let (words, bits) = (n / 64, n % 64);
+        Self(std::array::from_fn(|i| {
+            let Some(source) = i.checked_sub(words) else {
+                return 0;
+            };
+            let carry = match (bits, source.checked_sub(1)) {
+                (0, _) | (_, None) => 0,
+                (_, Some(lower)) => self.0[lower] >> (64 - bits),
+            };
+            self.0[source] << bits | carry
+        }))
+    }
+
+    fn shr(self, n: usize) -> Self {
+        let (words, bits) = (n / 64, n % 64);
+        Self(std::array::from_fn(|i| {
+            let source = i + words;
+            if source >= W {
+                return 0;
+            }
+            let carry = match bits {
+                0 => 0,
+                _ if source + 1 < W => self.0[source + 1] << (64 - bits),
+                _ => 0,
+            };
+            self.0[source] >> bits | carry
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    fn to_u128(band: Words<2>) -> u128 {
+        band.0[0] as u128 | (band.0[1] as u128) << 64
+    }
+
+    #[test]
+    fn test_words_match_u128() {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        for _ in 0..1000 {
+            let value: u128 = rng.random();
+            let words = Words::<2>::from_le_bytes(&value.to_le_bytes());
+            assert_eq!(to_u128(words), value);
+
+            let n = rng.random_range(0..=130);
+            assert_eq!(to_u128(words.shl(n)), value.shl(n));
+            assert_eq!(to_u128(words.shr(n)), value.shr(n));
+            assert_eq!(words.trailing_zeros(), Band::trailing_zeros(&value));
+            let index = rng.random_range(0..128);
+            assert_eq!(words.bit(index), value.bit(index));
+            assert_eq!(words.word(1), value.word(1));
+            assert!(words
+                .nonzero()
+                .map(|(i, _)| i)
+                .eq(Row::nonzero(&value).map(|(i, _)| i)));
+        }
+    }
+
+    #[test]
+    fn test_zero_band() {
+        assert!(Words::<4>([0; 4]).is_zero());
+        assert_eq!(Words::<4>([0; 4]).trailing_zeros(), 256);
+        assert_eq!(Band::trailing_zeros(&0u64), 64);
+        assert_eq!(Words::<8>::from_le_bytes(&[0, 0, 1]).trailing_zeros(), 16);
+        // bits shifted out are dropped
+        assert!(Words::<4>([0, 0, 0, 1 << 63]).shl(1).is_zero());
+        assert!(1u64.shr(64).is_zero());
+        assert_eq!(Words::<4>([0; 4]).ones().count(), 0);
+        assert!(Words::<2>([0b101, 1 << 63]).ones().eq([0, 2, 127]));
+    }
+}
@@ -7,6 +7,19 @@ pub enum Error {
     #[error("Row {0} is 0")]
     ZeroRow(usize),
 
+    #[error("Pairs {0} and {1} have the same key")]
+    DuplicateKey(usize, usize),
+
+    #[error("Value {index} has {found} bytes, expected {expected}")]
+    ValueSize {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+
     #[error("Decode error: {0}")]
-    Decode(usize),
+    Decode(String),
+
+    #[error("Infeasible parameters: {0}")]
+    Parameters(String),
 }
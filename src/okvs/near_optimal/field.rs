@@ -0,0 +1,332 @@
+//! Field-valued OKVS values, for protocols that need the values in GF(2^128) or in a
+//! prime field, e.g., VOLE-based OPRFs or arithmetic shares.
+//!
+//! The band of a key is always a bit vector, i.e., a key decodes to the sum of the
+//! values at the set bits. In a field of characteristic 2, such as GF(2^128), adding
+//! is XOR and elimination keeps the rows bit vectors, so these values are encoded like
+//! byte strings. In a prime field, elimination subtracts multiples of rows, so the rows
+//! are stored as field elements while encoding.
+
+use super::band::{Band, Row};
+use super::okvs::OkvsV;
+use ark_ff::{BigInteger, PrimeField};
+use rand::{Rng, RngCore};
+use std::fmt::Debug;
+use std::ops::Mul;
+
+/// The coefficients of the matrix rows for values of a field.
+pub trait Scalar: Copy + PartialEq + Debug {
+    /// The row type used for elimination, given the bit vector type of the band.
+    type Row<B: Band>: Row<Scalar = Self>;
+
+    /// The row of a band of `width` bits.
+    fn row<B: Band>(band: B, width: usize) -> Self::Row<B>;
+
+    fn one() -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    fn neg(self) -> Self;
+
+    fn mul(self, other: Self) -> Self;
+
+    /// The multiplicative inverse. Panics for zero.
+    fn inverse(self) -> Self;
+}
+
+/// GF(2), where the rows stay bit vectors.
+impl Scalar for bool {
+    type Row<B: Band> = B;
+
+    fn row<B: Band>(band: B, _width: usize) -> B {
+        band
+    }
+
+    fn one() -> Self {
+        true
+    }
+
+    fn is_zero(&self) -> bool {
+        !*self
+    }
+
+    fn neg(self) -> Self {
+        self
+    }
+
+    fn mul(self, other: Self) -> Self {
+        self & other
+    }
+
+    fn inverse(self) -> Self {
+        assert!(self, "zero has no inverse");
+        self
+    }
+}
+
+/// An element of GF(2^128) in the polynomial basis modulo x^128 + x^7 + x^2 + x + 1,
+/// where bit i is the coefficient of x^i. As an OKVS value, it is a vector over GF(2),
+/// and since the bands are binary, decoding is GF(2^128)-linear: multiplying all
+/// values of an encoding by a field element multiplies every decoded value by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Gf128(pub u128);
+
+impl Mul for Gf128 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let (mut a, mut b, mut product) = (self.0, other.0, 0u128);
+        while b != 0 {
+            if b & 1 == 1 {
+                product ^= a;
+            }
+            b >>= 1;
+            let carry = a >> 127;
+            a <<= 1;
+            if carry == 1 {
+                a ^= 0x87;
+            }
+        }
+        Self(product)
+    }
+}
+
+impl OkvsV for Gf128 {
+    type Scalar = bool;
+
+    fn default() -> Self {
+        Self(0)
+    }
+
+    fn random(rng: &mut impl RngCore) -> Self {
+        Self(rng.random())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Self(self.0 ^ other.0)
+    }
+
+    fn in_place_add(&mut self, other: &Self) {
+        self.0 ^= other.0;
+    }
+
+    fn scale(&self, scalar: &bool) -> Self {
+        if *scalar {
+            *self
+        } else {
+            Self(0)
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_le_bytes().into()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes
+            .try_into()
+            .ok()
+            .map(|bytes| Self(u128::from_le_bytes(bytes)))
+    }
+}
+
+/// An element of a prime field, e.g., the scalar field of a curve from `ark_ff`. The
+/// field is its own field of coefficients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldValue<F: PrimeField>(pub F);
+
+impl<F: PrimeField> FieldValue<F> {
+    /// The size of an element in bytes.
+    fn size() -> usize {
+        F::MODULUS.to_bytes_le().len()
+    }
+}
+
+impl<F: PrimeField> Scalar for FieldValue<F> {
+    type Row<B: Band> = FieldRow<F>;
+
+    fn row<B: Band>(band: B, width: usize) -> FieldRow<F> {
+        FieldRow(
+            (0..width)
+                .map(|i| if band.bit(i) { F::ONE } else { F::ZERO })
+                .collect(),
+        )
+    }
+
+    fn one() -> Self {
+        Self(F::ONE)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+
+    fn mul(self, other: Self) -> Self {
+        Self(self.0 * other.0)
+    }
+
+    fn inverse(self) -> Self {
+        Self(self.0.inverse().expect("zero has no inverse"))
+    }
+}
+
+impl<F: PrimeField> OkvsV for FieldValue<F> {
+    type Scalar = Self;
+
+    fn default() -> Self {
+        Self(F::ZERO)
+    }
+
+    /// Reduces 128 more random bits than the modulus has, so the bias is negligible.
+    fn random(rng: &mut impl RngCore) -> Self {
+        let mut bytes = vec![0u8; Self::size() + 16];
+        rng.fill_bytes(&mut bytes);
+        Self(F::from_le_bytes_mod_order(&bytes))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Self(self.0 + other.0)
+    }
+
+    fn in_place_add(&mut self, other: &Self) {
+        self.0 += other.0;
+    }
+
+    fn scale(&self, scalar: &Self) -> Self {
+        Self(self.0 * scalar.0)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.into_bigint().to_bytes_le()
+    }
+
+    /// Only accepts the canonical little-endian encoding, i.e., less than the modulus.
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::size() {
+            return None;
+        }
+        let value = F::from_le_bytes_mod_order(bytes);
+        (value.into_bigint().to_bytes_le() == bytes).then_some(Self(value))
+    }
+}
+
+/// A row with coefficients in a prime field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldRow<F: PrimeField>(Vec<F>);
+
+impl<F: PrimeField> Row for FieldRow<F> {
+    type Scalar = FieldValue<F>;
+
+    fn leading(&self) -> Option<usize> {
+        self.0.iter().position(|c| !c.is_zero())
+    }
+
+    fn coefficient(&self, index: usize) -> FieldValue<F> {
+        FieldValue(self.0[index])
+    }
+
+    fn add_scaled(
+        &mut self,
+        other: &Self,
+        factor: FieldValue<F>,
+        start_other: usize,
+        start_self: usize,
+    ) {
+        // coefficient j of this row is in the column of coefficient j + shift of other
+        let (to, from) = if start_other >= start_self {
+            (0, start_other - start_self)
+        } else {
+            (start_self - start_other, 0)
+        };
+        for (c, o) in self.0[to..].iter_mut().zip(&other.0[from..]) {
+            *c += factor.0 * o;
+        }
+    }
+
+    fn nonzero(&self) -> impl Iterator<Item = (usize, FieldValue<F>)> {
+        self.0
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.is_zero())
+            .map(|(i, c)| (i, FieldValue(*c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::okvs::{Encoding, Okvs, OkvsKey, RbOkvs};
+    use super::super::params::RbOkvsParams;
+    use super::*;
+    use ark_test_curves::bls12_381::Fr;
+
+    fn assert_roundtrip<V: OkvsV + Debug>(okvs: &RbOkvs, value: impl Fn(u64) -> V) {
+        let input: Vec<_> = (0..okvs.columns() as u64 * 9 / 10)
+            .map(|i| (OkvsKey(i.to_le_bytes()), value(i)))
+            .collect();
+        let encoding = okvs.encode(input.clone()).unwrap();
+        for (key, value) in &input {
+            assert_eq!(&okvs.decode(&encoding, key).unwrap(), value);
+        }
+
+        let decoded = Encoding::<V>::from_bytes(&encoding.to_bytes()).unwrap();
+        assert_eq!(decoded, encoding);
+    }
+
+    #[test]
+    fn test_gf128_roundtrip() {
+        let value = |i: u64| Gf128(((i as u128) << 64) | (3 * i as u128));
+        assert_roundtrip(&RbOkvs::new(30), value);
+        assert_roundtrip(&RbOkvs::new(2000), value);
+    }
+
+    #[test]
+    fn test_prime_field_roundtrip() {
+        let value = |i: u64| FieldValue(Fr::from(i) * Fr::from(i) - Fr::from(5u64));
+        assert_roundtrip(&RbOkvs::new(30), value);
+        assert_roundtrip(&RbOkvs::new(2000), value);
+        // the narrowest banded configuration of the fits
+        let params = RbOkvsParams::for_speed(10, 128).unwrap();
+        assert_roundtrip(&RbOkvs::with_params(2000, params), value);
+    }
+
+    #[test]
+    fn test_prime_field_bytes() {
+        assert_eq!(FieldValue::<Fr>::size(), 32);
+        let value = FieldValue(Fr::from(42u64));
+        assert_eq!(FieldValue::from_bytes(&value.to_bytes()), Some(value));
+        // the modulus is not canonical
+        assert_eq!(
+            FieldValue::<Fr>::from_bytes(&Fr::MODULUS.to_bytes_le()),
+            None
+        );
+        assert_eq!(FieldValue::<Fr>::from_bytes(&[0; 31]), None);
+    }
+
+    #[test]
+    fn test_gf128_mul() {
+        let x = Gf128(2);
+        // x^127 * x = x^128 = x^7 + x^2 + x + 1
+        assert_eq!(Gf128(1 << 127) * x, Gf128(0x87));
+        let (a, b, c) = (
+            Gf128(0x1234_5678 << 70 | 99),
+            Gf128(u128::MAX / 3),
+            Gf128(77),
+        );
+        assert_eq!(a * b, b * a);
+        assert_eq!(a * Gf128(1), a);
+        assert_eq!(a * b.add(&c), (a * b).add(&(a * c)));
+        assert_eq!((a * b) * c, a * (b * c));
+    }
+}
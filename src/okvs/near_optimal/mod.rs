@@ -1,2 +1,6 @@
+pub mod band;
 pub mod error;
+pub mod field;
 pub mod okvs;
+pub mod params;
+pub mod values;
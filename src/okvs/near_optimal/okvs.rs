@@ -2,119 +2,483 @@
 //! Because the original authors have deprecated feature flags and old code, we cannot directly
 //! use the dependency. Thus, the code is copied and modified to fit our needs.
 
-use super::error::Result;
-use sp_core::U256;
-
-/// For small encoding sizes (i.e., high rate), one should try to fix small
-/// choices of ϵ such as 0.03-0.05 In contrast, if one wishes for an
-/// instantiation of RB-OKVS with fast encoding/decoding times, then one can
-/// pick larger values of ϵ such as 0.07-0.1.
-const EPSILON: f64 = 0.1;
-const _LAMBDA: usize = 20;
-const BAND_WIDTH: usize = 128; // ((LAMBDA as f64 + 15.21) / 0.2691) as usize = 130
+use super::band::{Band, Words};
+use super::error::{Error, Result};
+use super::field::Scalar;
+use super::params::{RbOkvsParams, MAX_BAND_WIDTH};
+use super::values::{self, ByteValues, Bytes, Value, Values};
+use crate::okvs::wire::{self, Kind, Reader, Writer};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::collections::hash_map::{Entry, HashMap};
+use std::fmt::Debug;
+
+/// The number of columns more than pairs of a dense encoding. A random binary matrix
+/// with n rows and n + k columns has full rank except with probability about 2^-k.
+const DENSE_MARGIN: usize = 40;
+
+/// How many times encoding is retried with a fresh seed after a zero row by default.
+const DEFAULT_RETRIES: usize = 8;
 
 /// RB-OKVS, Oblivious Key-Value Stores
+#[derive(Debug, Clone)]
 pub struct RbOkvs {
     columns: usize,
     band_width: usize,
+    seed: u64,
+    retries: usize,
+    merge_duplicates: bool,
 }
 
 impl RbOkvs {
+    /// RB-OKVS for up to `kv_count` pairs with the default parameters.
     pub fn new(kv_count: usize) -> RbOkvs {
-        let columns = ((1.0 + EPSILON) * kv_count as f64) as usize;
+        Self::with_params(kv_count, RbOkvsParams::default())
+    }
+
+    /// RB-OKVS for up to `kv_count` pairs with the given ε and band width, e.g., from
+    /// [`RbOkvsParams::for_rate`] or [`RbOkvsParams::for_speed`].
+    ///
+    /// The encoding has at least `DENSE_MARGIN` more columns than pairs. For small sets,
+    /// where the band would cover all columns, the band spans the whole row, i.e., the
+    /// system is solved by dense Gaussian elimination, which fails with negligible
+    /// probability for any number of pairs.
+    ///
+    /// Panics if the band width is not a multiple of 8 or wider than [`MAX_BAND_WIDTH`].
+    pub fn with_params(kv_count: usize, params: RbOkvsParams) -> RbOkvs {
+        assert!(
+            params.band_width > 0
+                && params.band_width <= MAX_BAND_WIDTH
+                && params.band_width.is_multiple_of(8),
+            "the band width must be a multiple of 8 between 8 and {}, got {}",
+            MAX_BAND_WIDTH,
+            params.band_width
+        );
+
+        let columns = (((1.0 + params.epsilon) * kv_count as f64).ceil() as usize)
+            .max(kv_count + DENSE_MARGIN);
+
+        if columns <= params.band_width {
+            let band_width = columns.next_multiple_of(8);
+            return Self {
+                columns: band_width,
+                band_width,
+                seed: 0,
+                retries: DEFAULT_RETRIES,
+                merge_duplicates: false,
+            };
+        }
 
         Self {
             columns,
-            band_width: if BAND_WIDTH < columns {
-                BAND_WIDTH
-            } else {
-                core::cmp::max(8, columns * 80 / 100)
-            },
+            band_width: params.band_width,
+            seed: 0,
+            retries: DEFAULT_RETRIES,
+            merge_duplicates: false,
         }
     }
+
+    /// Uses the given seed for the first encoding attempt.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Retries encoding up to `retries` times with a fresh seed if the system of a
+    /// seed has no solution. Zero disables retries.
+    pub fn with_retries(self, retries: usize) -> Self {
+        Self { retries, ..self }
+    }
+
+    /// Whether pairs with the same key and the same value are encoded once instead of
+    /// being rejected. Pairs with the same key and different values are always
+    /// rejected, since no encoding decodes the key to both values.
+    pub fn with_merge_duplicates(self, merge_duplicates: bool) -> Self {
+        Self {
+            merge_duplicates,
+            ..self
+        }
+    }
+
+    /// Whether every row spans all columns.
+    pub fn is_dense(&self) -> bool {
+        self.band_width == self.columns
+    }
+
+    /// The number of possible start columns of a band.
+    fn starts(&self) -> usize {
+        self.columns - self.band_width + 1
+    }
+
+    /// The number of values in an encoding.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn band_width(&self) -> usize {
+        self.band_width
+    }
 }
 
 impl Okvs for RbOkvs {
+    /// Encodes the pairs with the seed of the OKVS. If the system has no solution
+    /// (i.e., elimination hits a zero row), encoding is retried with fresh seeds.
+    /// The encoding records the seed it was encoded with. Fails with the last
+    /// [`Error::ZeroRow`] if all retries fail.
+    ///
+    /// The columns that no pair determines are drawn from a generator seeded with the
+    /// seed, so the same pairs and seed always give the same encoding.
+    ///
+    /// Fails with [`Error::DuplicateKey`] if two pairs have the same key, unless
+    /// duplicates are merged and the values are the same.
     fn encode<K: OkvsK, V: OkvsV>(&self, input: Vec<Pair<K, V>>) -> Result<Encoding<V>> {
-        let (matrix, start_pos, y) = self.create_sorted_matrix(input)?;
-        utils::simple_gauss::<V>(y, matrix, start_pos, self.columns)
+        let (keys, values) = self.check_duplicates(input)?.into_iter().unzip();
+        self.encode_values(keys, values)
     }
 
-    fn decode<V: OkvsV>(&self, encoding: &Encoding<V>, key: &impl OkvsK) -> V {
-        let start = key.hash_to_index(self.columns - self.band_width);
-        let band = key.hash_to_band(self.band_width);
-        utils::inner_product(&band, &encoding[start..])
+    /// Fails with [`Error::Decode`] if the encoding is not an encoding of an OKVS with
+    /// the parameters of this one.
+    fn decode<V: Value>(
+        &self,
+        encoding: &Encoding<V>,
+        key: &impl OkvsK,
+    ) -> Result<<V::Values as Values>::Value> {
+        if encoding.columns() != self.columns || encoding.band_width != self.band_width {
+            return Err(Error::Decode(format!(
+                "the encoding has {} columns and a band of {} bits, expected {} and {}",
+                encoding.columns(),
+                encoding.band_width,
+                self.columns,
+                self.band_width
+            )));
+        }
+
+        Ok(match self.band_width {
+            0..=64 => self.decode_with::<u64, V>(encoding, key),
+            65..=128 => self.decode_with::<u128, V>(encoding, key),
+            129..=256 => self.decode_with::<Words<4>, V>(encoding, key),
+            257..=512 => self.decode_with::<Words<8>, V>(encoding, key),
+            _ => self.decode_with::<Words<16>, V>(encoding, key),
+        })
     }
 }
 
 impl RbOkvs {
-    fn create_sorted_matrix<K: OkvsK, V: OkvsV>(
+    /// Encodes byte strings of `value_size` bytes each, which is chosen at runtime.
+    /// The values are stored back to back in one buffer (see [`ByteValues`]), and a
+    /// key decodes to a `Vec<u8>` of `value_size` bytes.
+    ///
+    /// Fails with [`Error::ValueSize`] if a value has another size, and like
+    /// [`Okvs::encode`] otherwise.
+    pub fn encode_bytes<K: OkvsK>(
+        &self,
+        input: Vec<Pair<K, &[u8]>>,
+        value_size: usize,
+    ) -> Result<Encoding<Bytes>> {
+        if value_size == 0 {
+            return Err(Error::Parameters("values must not be empty".to_string()));
+        }
+        if let Some((index, (_, value))) = input
+            .iter()
+            .enumerate()
+            .find(|(_, (_, value))| value.len() != value_size)
+        {
+            return Err(Error::ValueSize {
+                index,
+                expected: value_size,
+                found: value.len(),
+            });
+        }
+
+        let input = self.check_duplicates(input)?;
+        let mut values = ByteValues::with_capacity(value_size, input.len());
+        let keys = input
+            .into_iter()
+            .map(|(key, value)| {
+                values.push(value);
+                key
+            })
+            .collect();
+        self.encode_values(keys, values)
+    }
+
+    /// Rejects pairs with the same key, or drops them if they are merged and have the
+    /// value of the first pair with the key.
+    fn check_duplicates<K: OkvsK, V: PartialEq>(
         &self,
         input: Vec<Pair<K, V>>,
-    ) -> Result<(Vec<U256>, Vec<usize>, Vec<V>)> {
-        let n = input.len();
+    ) -> Result<Vec<Pair<K, V>>> {
+        let mut first: HashMap<Vec<u8>, usize> = HashMap::with_capacity(input.len());
+        let mut duplicates = Vec::new();
+        for (i, (key, value)) in input.iter().enumerate() {
+            match first.entry(key.to_bytes()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(i);
+                }
+                Entry::Occupied(entry) => {
+                    let j = *entry.get();
+                    if !self.merge_duplicates || *value != input[j].1 {
+                        return Err(Error::DuplicateKey(j, i));
+                    }
+                    duplicates.push(i);
+                }
+            }
+        }
+
+        if duplicates.is_empty() {
+            return Ok(input);
+        }
+        let mut duplicates = duplicates.into_iter().peekable();
+        Ok(input
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| duplicates.next_if_eq(i).is_none())
+            .map(|(_, pair)| pair)
+            .collect())
+    }
+
+    /// Encodes the keys with the values of the same index.
+    fn encode_values<K: OkvsK, V: Value>(
+        &self,
+        keys: Vec<K>,
+        values: V::Values,
+    ) -> Result<Encoding<V>> {
+        match self.band_width {
+            0..=64 => self.encode_with::<u64, K, V>(&keys, &values),
+            65..=128 => self.encode_with::<u128, K, V>(&keys, &values),
+            129..=256 => self.encode_with::<Words<4>, K, V>(&keys, &values),
+            257..=512 => self.encode_with::<Words<8>, K, V>(&keys, &values),
+            _ => self.encode_with::<Words<16>, K, V>(&keys, &values),
+        }
+    }
+
+    /// Encodes with bands of type B, the narrowest type that holds the band width.
+    fn encode_with<B: Band, K: OkvsK, V: Value>(
+        &self,
+        keys: &[K],
+        values: &V::Values,
+    ) -> Result<Encoding<V>> {
+        let mut seed = self.seed;
+        let mut attempt = 0;
+        loop {
+            let (matrix, start_pos, order) = self.create_sorted_matrix::<B, K>(keys, seed)?;
+            let rows: Vec<_> = matrix
+                .into_iter()
+                .map(|band| <V::Values as Values>::Scalar::row(band, self.band_width))
+                .collect();
+            let y = values.select(&order);
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            match utils::simple_gauss(y, rows, start_pos, self.columns, &mut rng) {
+                Ok(values) => {
+                    return Ok(Encoding {
+                        seed,
+                        band_width: self.band_width,
+                        values,
+                    })
+                }
+                Err(Error::ZeroRow(_)) if attempt < self.retries => {
+                    attempt += 1;
+                    seed = u64::from_le_bytes(utils::blake2b::<8>(&seed.to_le_bytes()));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn decode_with<B: Band, V: Value>(
+        &self,
+        encoding: &Encoding<V>,
+        key: &impl OkvsK,
+    ) -> <V::Values as Values>::Value {
+        let start = key.hash_to_index(encoding.seed(), self.starts());
+        let band: B = key.hash_to_band(encoding.seed(), self.band_width);
+        encoding.values.inner_product(&band, start)
+    }
+
+    /// The bands and start columns of the keys sorted by start column, and the index of
+    /// the key of each row.
+    fn create_sorted_matrix<B: Band, K: OkvsK>(
+        &self,
+        keys: &[K],
+        seed: u64,
+    ) -> Result<(Vec<B>, Vec<usize>, Vec<usize>)> {
+        let n = keys.len();
         let mut start_pos: Vec<(usize, usize)> = vec![(0, 0); n];
 
-        input.iter().enumerate().for_each(|(i, (k, _))| {
-            start_pos[i] = (i, k.hash_to_index(self.columns - self.band_width))
-        });
+        keys.iter()
+            .enumerate()
+            .for_each(|(i, k)| start_pos[i] = (i, k.hash_to_index(seed, self.starts())));
 
-        utils::radix_sort(&mut start_pos, self.columns - self.band_width - 1);
+        utils::radix_sort(&mut start_pos, self.starts() - 1);
 
-        let mut matrix: Vec<U256> = vec![U256::default(); n];
+        let mut matrix: Vec<B> = vec![B::from_le_bytes(&[]); n];
         let mut start_ids: Vec<usize> = vec![0; n];
-        let mut y: Vec<V> = vec![V::default(); n];
+        let mut order: Vec<usize> = vec![0; n];
 
         // Generate binary matrix
         start_pos
             .into_iter()
             .enumerate()
             .for_each(|(k, (i, start))| {
-                matrix[k] = input[i].0.hash_to_band(self.band_width);
-                y[k] = input[i].1.to_owned();
+                matrix[k] = keys[i].hash_to_band(seed, self.band_width);
+                order[k] = i;
                 start_ids[k] = start;
             });
 
-        Ok((matrix, start_ids, y))
+        Ok((matrix, start_ids, order))
+    }
+}
+
+/// The values of an encoding together with the parameters it was encoded with: the
+/// hash seed, the band width and the number of columns, i.e., of values.
+/// An encoding is only built by encoding pairs or by reading the wire format, which
+/// validates the parameters, so decoding it never reads outside of the values.
+///
+/// The values are a `Vec<V>` for values of an [`OkvsV`] type, and [`ByteValues`] for
+/// [`Bytes`], i.e., byte strings of a size chosen at runtime.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoding<T: Value> {
+    seed: u64,
+    band_width: usize,
+    values: T::Values,
+}
+
+impl<V: Value> Encoding<V> {
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn band_width(&self) -> usize {
+        self.band_width
+    }
+
+    pub fn columns(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &V::Values {
+        &self.values
+    }
+
+    /// The RB-OKVS with the parameters of the encoding, which decodes it.
+    pub fn okvs(&self) -> RbOkvs {
+        RbOkvs {
+            columns: self.columns(),
+            band_width: self.band_width,
+            seed: self.seed,
+            retries: DEFAULT_RETRIES,
+            merge_duplicates: false,
+        }
+    }
+
+    /// Serializes the encoding with its parameters in the [wire format](crate::okvs::wire).
+    /// Panics if the values differ in size.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::new(Kind::RbOkvs);
+        writer.u64(self.columns() as u64);
+        writer.u32(self.band_width as u32);
+        writer.u64(self.seed);
+        writer.u32(self.values.value_size() as u32);
+        writer.bytes(&self.values.to_bytes());
+        writer.finish()
+    }
+
+    /// The encoding of bytes in the [wire format](crate::okvs::wire). Fails if the
+    /// header is invalid, the parameters do not describe an RB-OKVS with values of
+    /// type V, or the payload does not match the parameters.
+    pub fn from_bytes(bytes: &[u8]) -> wire::error::Result<Self> {
+        let mut reader = Reader::new(bytes, Kind::RbOkvs)?;
+        let columns = reader.len()?;
+        let band_width = reader.u32()? as usize;
+        let seed = reader.u64()?;
+        let value_size = reader.u32()? as usize;
+
+        if band_width == 0
+            || band_width > MAX_BAND_WIDTH
+            || !band_width.is_multiple_of(8)
+            || band_width > columns
+        {
+            return Err(wire::error::Error::Parameters(format!(
+                "invalid band width {} for {} columns",
+                band_width, columns
+            )));
+        }
+        if value_size == 0 {
+            return Err(wire::error::Error::Parameters(
+                "values must not be empty".to_string(),
+            ));
+        }
+
+        let values =
+            V::Values::from_bytes(reader.items(columns, value_size)?, columns, value_size)?;
+        reader.finish()?;
+
+        Ok(Self {
+            seed,
+            band_width,
+            values,
+        })
     }
 }
 
-pub type Encoding<T> = Vec<T>;
 pub type Pair<K, V> = (K, V);
 
 pub trait Okvs {
     fn encode<K: OkvsK, V: OkvsV>(&self, input: Vec<Pair<K, V>>) -> Result<Encoding<V>>;
-    fn decode<V: OkvsV>(&self, encoding: &Encoding<V>, key: &impl OkvsK) -> V;
+    fn decode<V: Value>(
+        &self,
+        encoding: &Encoding<V>,
+        key: &impl OkvsK,
+    ) -> Result<<V::Values as Values>::Value>;
 }
 
 pub trait OkvsK {
-    fn hash_to_index(&self, range: usize) -> usize;
-    fn hash_to_band(&self, band_width: usize) -> U256;
+    fn hash_to_index(&self, seed: u64, range: usize) -> usize;
+    /// The band of `band_width` bits, which must fit into B.
+    fn hash_to_band<B: Band>(&self, seed: u64, band_width: usize) -> B;
     fn to_bytes(&self) -> Vec<u8>;
 }
 
-pub trait OkvsV: Clone {
+/// Values of an OKVS, which form a vector space over the field of the coefficients of
+/// the matrix, e.g., byte strings with XOR over GF(2) (see [`OkvsValue`]) or the
+/// elements of a prime field over the field itself (see
+/// [`FieldValue`](super::field::FieldValue)).
+pub trait OkvsV: Clone + PartialEq + Debug {
+    type Scalar: Scalar;
+
+    /// The zero value.
     fn default() -> Self;
+    /// A uniformly random value, used for the columns that no pair determines.
+    fn random(rng: &mut impl RngCore) -> Self;
     fn is_zero(&self) -> bool;
-    fn xor(&self, other: &Self) -> Self;
-    fn in_place_xor(&mut self, other: &Self);
+    fn add(&self, other: &Self) -> Self;
+    fn in_place_add(&mut self, other: &Self);
+    fn scale(&self, scalar: &Self::Scalar) -> Self;
+
+    /// Adds `scalar` times `other`.
+    fn in_place_add_scaled(&mut self, other: &Self, scalar: &Self::Scalar) {
+        self.in_place_add(&other.scale(scalar));
+    }
+
+    fn to_bytes(&self) -> Vec<u8>;
+    /// The value of the bytes, or `None` if they do not represent a value.
+    fn from_bytes(bytes: &[u8]) -> Option<Self>;
 }
 
 #[derive(Clone)]
 pub struct OkvsKey<const N: usize = 8>(pub [u8; N]);
 
 impl<const N: usize> OkvsK for OkvsKey<N> {
-    /// hash1(key) -> [0, range)
-    fn hash_to_index(&self, range: usize) -> usize {
-        let v = utils::blake2b::<8>(&self.to_bytes());
+    /// hash1(seed, key) -> [0, range)
+    fn hash_to_index(&self, seed: u64, range: usize) -> usize {
+        let v = utils::blake2b::<8>(&[&seed.to_le_bytes(), &self.0[..]].concat());
         usize::from_le_bytes(v) % range
     }
 
-    /// hash2(key) -> {0, 1}^band_width
-    fn hash_to_band(&self, band_width: usize) -> U256 {
-        let mut v = utils::hash(&self.0, band_width / 8);
+    /// hash2(seed, key) -> {0, 1}^band_width
+    fn hash_to_band<B: Band>(&self, seed: u64, band_width: usize) -> B {
+        let mut v = utils::hash(&[&seed.to_le_bytes(), &self.0[..]].concat(), band_width / 8);
         v[0] |= 1;
-        U256::from_little_endian(&v)
+        B::from_le_bytes(&v)
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -126,10 +490,18 @@ impl<const N: usize> OkvsK for OkvsKey<N> {
 pub struct OkvsValue<const N: usize = 8>(pub [u8; N]);
 
 impl<const N: usize> OkvsV for OkvsValue<N> {
+    type Scalar = bool;
+
     fn default() -> Self {
         Self([0u8; N])
     }
 
+    fn random(rng: &mut impl RngCore) -> Self {
+        let mut value = [0u8; N];
+        rng.fill_bytes(&mut value);
+        Self(value)
+    }
+
     fn is_zero(&self) -> bool {
         for v in &self.0 {
             if *v != 0 {
@@ -139,225 +511,106 @@ impl<const N: usize> OkvsV for OkvsValue<N> {
         true
     }
 
-    fn xor(&self, other: &Self) -> Self {
-        let mut result = [0u8; N];
-        for (i, item) in self.0.iter().enumerate() {
-            result[i] = item ^ other.0[i];
+    fn add(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        result.in_place_add(other);
+        result
+    }
+
+    fn in_place_add(&mut self, other: &Self) {
+        values::xor(&mut self.0, &other.0);
+    }
+
+    fn scale(&self, scalar: &bool) -> Self {
+        if *scalar {
+            self.clone()
+        } else {
+            Self::default()
         }
-        Self(result)
     }
 
-    fn in_place_xor(&mut self, other: &Self) {
-        for i in 0..self.0.len() {
-            self.0[i] ^= other.0[i];
+    fn in_place_add_scaled(&mut self, other: &Self, scalar: &bool) {
+        if *scalar {
+            self.in_place_add(other);
         }
     }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.into()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Self)
+    }
 }
 
 mod utils {
-    use blake2::{Blake2b512, Digest};
-    use sp_core::U256;
-
+    use super::super::band::Row;
     use super::super::error::{Error, Result};
+    use super::super::field::Scalar;
+    use super::super::values::Values;
+    use blake2::{Blake2b512, Digest};
+    use rand::RngCore;
 
     /// Martin Dietzfelbinger and Stefan Walzer. Efficient Gauss Elimination for
     /// Near-Quadratic Matrices with One Short Random Block per Row, with
     /// Applications. In 27th Annual European Symposium on Algorithms (ESA 2019).
     /// Schloss Dagstuhl-Leibniz-Zentrum fuer Informatik, 2019.
-    pub fn simple_gauss<V: super::OkvsV>(
-        mut y: Vec<V>,
-        mut bands: Vec<U256>,
+    ///
+    /// Solves the system over the field of the values: each step adds a multiple of
+    /// the pivot row to the rows below, so that their coefficient in the pivot column
+    /// becomes zero.
+    pub fn simple_gauss<R: Row, S: Values<Scalar = R::Scalar>>(
+        mut y: S,
+        mut bands: Vec<R>,
         start_pos: Vec<usize>,
         cols: usize,
-    ) -> Result<Vec<V>> {
+        rng: &mut impl RngCore,
+    ) -> Result<S> {
         let rows = bands.len();
         assert_eq!(rows, start_pos.len());
         assert_eq!(rows, y.len());
         let mut pivot: Vec<usize> = vec![0; rows];
 
         for i in 0..rows {
-            let y_i = y[i].clone();
-
-            let first_one = bands[i].trailing_zeros() as usize;
-            if first_one == 256 {
+            let Some(first) = bands[i].leading() else {
                 return Err(Error::ZeroRow(i));
-            }
+            };
+            let inverse = bands[i].coefficient(first).inverse();
 
-            pivot[i] = first_one + start_pos[i];
+            pivot[i] = first + start_pos[i];
 
             for k in (i + 1)..rows {
                 if start_pos[k] > pivot[i] {
                     break;
                 }
-                if bit(&bands[k], pivot[i] - start_pos[k]) {
-                    bands[k] = xor(bands[i], bands[k], first_one, pivot[i] - start_pos[k]);
-                    y[k].in_place_xor(&y_i);
+                let coefficient = bands[k].coefficient(pivot[i] - start_pos[k]);
+                if !coefficient.is_zero() {
+                    let factor = coefficient.mul(inverse).neg();
+                    let (upper, lower) = bands.split_at_mut(k);
+                    lower[0].add_scaled(&upper[i], factor, first, pivot[i] - start_pos[k]);
+                    y.add_scaled(k, i, &factor);
                 }
             }
         }
 
         // back subsitution
-        let mut x = vec![V::default(); cols]; // solution to Ax = y
+        // Columns without a pivot are drawn from `rng`, so that decoding a key that is
+        // not encoded gives a random value instead of a combination of the encoded values.
+        let mut x = y.random_like(cols, rng); // solution to Ax = y
         for i in (0..rows).rev() {
-            x[pivot[i]] = inner_product::<V>(&bands[i], &x[start_pos[i]..]).xor(&y[i]);
-        }
-        Ok(x)
-    }
-
-    const MASK: [u64; 64] = [
-        0x1,
-        0x2,
-        0x4,
-        0x8,
-        0x10,
-        0x20,
-        0x40,
-        0x80,
-        0x100,
-        0x200,
-        0x400,
-        0x800,
-        0x1000,
-        0x2000,
-        0x4000,
-        0x8000,
-        0x10000,
-        0x20000,
-        0x40000,
-        0x80000,
-        0x100000,
-        0x200000,
-        0x400000,
-        0x800000,
-        0x1000000,
-        0x2000000,
-        0x4000000,
-        0x8000000,
-        0x10000000,
-        0x20000000,
-        0x40000000,
-        0x80000000,
-        0x100000000,
-        0x200000000,
-        0x400000000,
-        0x800000000,
-        0x1000000000,
-        0x2000000000,
-        0x4000000000,
-        0x8000000000,
-        0x10000000000,
-        0x20000000000,
-        0x40000000000,
-        0x80000000000,
-        0x100000000000,
-        0x200000000000,
-        0x400000000000,
-        0x800000000000,
-        0x1000000000000,
-        0x2000000000000,
-        0x4000000000000,
-        0x8000000000000,
-        0x10000000000000,
-        0x20000000000000,
-        0x40000000000000,
-        0x80000000000000,
-        0x100000000000000,
-        0x200000000000000,
-        0x400000000000000,
-        0x800000000000000,
-        0x1000000000000000,
-        0x2000000000000000,
-        0x4000000000000000,
-        0x8000000000000000,
-    ];
-
-    fn bit(u: &U256, index: usize) -> bool {
-        if index < 64 {
-            u.0[0] & MASK[index] != 0
-        } else if index < 128 {
-            u.0[1] & MASK[index - 64] != 0
-        } else if index < 192 {
-            u.0[2] & MASK[index - 128] != 0
-        } else {
-            u.0[3] & MASK[index - 192] != 0
-        }
-    }
-
-    fn xor(a: U256, b: U256, start_a: usize, start_b: usize) -> U256 {
-        match start_a.cmp(&start_b) {
-            std::cmp::Ordering::Equal => b ^ a,
-            std::cmp::Ordering::Less => {
-                let diff = start_b - start_a;
-                ((b >> diff) ^ a) << diff
-            }
-            std::cmp::Ordering::Greater => {
-                let diff = start_a - start_b;
-                ((b << diff) ^ a) >> diff
-            }
-        }
-    }
-
-    pub fn inner_product<V: super::OkvsV>(m: &U256, x: &[V]) -> V {
-        let mut result = V::default();
-        let bits = m.bits();
-
-        if bits <= 64 {
-            for i in 0..bits {
-                if m.0[0] & MASK[i] != 0 {
-                    result.in_place_xor(&x[i]);
+            let p = pivot[i];
+            x.set_zero(p);
+            x.add_scaled_from(p, &y, i, &R::Scalar::one());
+            for (j, coefficient) in bands[i].nonzero() {
+                if start_pos[i] + j != p {
+                    x.add_scaled(p, start_pos[i] + j, &coefficient.neg());
                 }
             }
-            return result;
+            let leading = bands[i].coefficient(p - start_pos[i]);
+            x.scale(p, &leading.inverse());
         }
-
-        for i in 0..64 {
-            if m.0[0] & MASK[i] != 0 {
-                result.in_place_xor(&x[i]);
-            }
-        }
-
-        let x64 = &x[64..];
-
-        if bits <= 128 {
-            for i in 0..bits - 64 {
-                if m.0[1] & MASK[i] != 0 {
-                    result.in_place_xor(&x64[i]);
-                }
-            }
-            return result;
-        }
-
-        for i in 0..64 {
-            if m.0[1] & MASK[i] != 0 {
-                result.in_place_xor(&x64[i]);
-            }
-        }
-
-        let x128 = &x[128..];
-
-        if bits <= 192 {
-            for i in 0..bits - 128 {
-                if m.0[2] & MASK[i] != 0 {
-                    result.in_place_xor(&x128[i]);
-                }
-            }
-            return result;
-        }
-
-        for i in 0..64 {
-            if m.0[2] & MASK[i] != 0 {
-                result.in_place_xor(&x128[i]);
-            }
-        }
-
-        let x192 = &x[192..];
-
-        for i in 0..bits - 192 {
-            if m.0[3] & MASK[i] != 0 {
-                result.in_place_xor(&x192[i]);
-            }
-        }
-        result
+        Ok(x)
     }
 
     pub fn blake2b<const N: usize>(data: &[u8]) -> [u8; N] {
@@ -381,17 +634,11 @@ mod utils {
             return res[0..to_bytes_size].into();
         }
 
-        let mut result = vec![];
-        let mut last_length = to_bytes_size;
-        let loop_count = (to_bytes_size + 63) / 64;
-
-        for i in 0..loop_count {
-            if i == loop_count - 1 {
-                result.extend(hash(data, last_length));
-            } else {
-                result.extend(hash(data, 64));
-                last_length -= 64;
-            }
+        // Longer outputs hash the data with the index of each block of 64 bytes.
+        let mut result = Vec::with_capacity(to_bytes_size);
+        for i in 0..to_bytes_size.div_ceil(64) {
+            let block = [data.as_ref(), &(i as u64).to_le_bytes()].concat();
+            result.extend(hash(&block, (to_bytes_size - 64 * i).min(64)));
         }
 
         result
@@ -409,7 +656,7 @@ mod utils {
         }
     }
 
-    fn count_sort(arr: &Vec<(usize, usize)>, exp: usize) -> Vec<(usize, usize)> {
+    fn count_sort(arr: &[(usize, usize)], exp: usize) -> Vec<(usize, usize)> {
         let mut count = [0usize; 10];
 
         arr.iter().for_each(|(_, b)| count[(b / exp) % 10] += 1);
@@ -428,3 +675,319 @@ mod utils {
         output
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(n: usize, seed: u64) -> Vec<Pair<OkvsKey, OkvsValue>> {
+        (0..n as u64)
+            .map(|i| {
+                let key = utils::blake2b::<8>(&[seed.to_le_bytes(), i.to_le_bytes()].concat());
+                (OkvsKey(key), OkvsValue((i + 1).to_le_bytes()))
+            })
+            .collect()
+    }
+
+    fn assert_roundtrip(okvs: &RbOkvs, pairs: Vec<Pair<OkvsKey, OkvsValue>>) {
+        let encoding = okvs.encode(pairs.clone()).unwrap();
+        assert_eq!(encoding.columns(), okvs.columns());
+        for (key, value) in &pairs {
+            assert_eq!(&okvs.decode(&encoding, key).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_small_sets() {
+        for n in 1..=64 {
+            let okvs = RbOkvs::new(n);
+            assert!(okvs.is_dense());
+            for seed in 0..20 {
+                assert_roundtrip(&okvs, pairs(n, seed));
+            }
+        }
+    }
+
+    #[test]
+    fn test_between_dense_and_banded() {
+        for n in [65, 88, 100, 120, 150, 200, 300] {
+            let okvs = RbOkvs::new(n);
+            for seed in 0..5 {
+                assert_roundtrip(&okvs, pairs(n, seed));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fewer_pairs_than_capacity() {
+        assert_roundtrip(&RbOkvs::new(0), vec![]);
+        assert_roundtrip(&RbOkvs::new(1000), pairs(10, 0));
+    }
+
+    #[test]
+    fn test_unknown_key_decodes_to_random_value() {
+        // the values are not random, so a combination of them would be recognizable
+        let pairs: Vec<_> = (1..=3u64)
+            .map(|i| (OkvsKey(i.to_le_bytes()), OkvsValue(i.to_le_bytes())))
+            .collect();
+        let okvs = RbOkvs::new(3);
+        let encoding = okvs.encode(pairs).unwrap();
+        let value = okvs
+            .decode(&encoding, &OkvsKey(100u64.to_le_bytes()))
+            .unwrap();
+        assert!(u64::from_le_bytes(value.0) > 3);
+    }
+
+    #[test]
+    fn test_retry_with_fresh_seed() {
+        // A band of 16 bits is too narrow for 300 pairs, so most seeds fail.
+        let params = RbOkvsParams {
+            epsilon: 0.1,
+            band_width: 16,
+        };
+        let okvs = RbOkvs::with_params(300, params).with_retries(0);
+        let failing = (0..100u64)
+            .find(|&seed| okvs.encode(pairs(300, seed)).is_err())
+            .unwrap();
+
+        let okvs = RbOkvs::with_params(300, params).with_retries(100);
+        let input = pairs(300, failing);
+        let encoding = okvs.encode(input.clone()).unwrap();
+        assert_ne!(encoding.seed(), 0);
+        for (key, value) in &input {
+            assert_eq!(&okvs.decode(&encoding, key).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_retries_exhausted() {
+        // a band of 8 bits is far too narrow for 300 pairs
+        let params = RbOkvsParams {
+            epsilon: 0.1,
+            band_width: 8,
+        };
+        let okvs = RbOkvs::with_params(300, params).with_retries(3);
+        assert!(matches!(okvs.encode(pairs(300, 0)), Err(Error::ZeroRow(_))));
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let key = OkvsKey(1u64.to_le_bytes());
+        let mut input = pairs(10, 0);
+        input.insert(3, (key.clone(), OkvsValue(1u64.to_le_bytes())));
+        input.push((key.clone(), OkvsValue(2u64.to_le_bytes())));
+
+        // different values are rejected even if duplicates are merged
+        let okvs = RbOkvs::new(12);
+        assert!(matches!(
+            okvs.encode(input.clone()),
+            Err(Error::DuplicateKey(3, 11))
+        ));
+        let okvs = okvs.with_merge_duplicates(true);
+        assert!(matches!(
+            okvs.encode(input.clone()),
+            Err(Error::DuplicateKey(3, 11))
+        ));
+
+        // the same value is rejected unless duplicates are merged
+        input[11].1 = OkvsValue(1u64.to_le_bytes());
+        input.push((key, OkvsValue(1u64.to_le_bytes())));
+        assert!(matches!(
+            RbOkvs::new(13).encode(input.clone()),
+            Err(Error::DuplicateKey(3, 11))
+        ));
+        assert_roundtrip(&okvs, input);
+    }
+
+    #[test]
+    fn test_band_widths() {
+        for band_width in [64, 72, 128, 200, 256, 384, 512, 1024] {
+            let params = RbOkvsParams {
+                epsilon: 0.1,
+                band_width,
+            };
+            let okvs = RbOkvs::with_params(3000, params);
+            assert_eq!(okvs.band_width(), band_width);
+            assert_roundtrip(&okvs, pairs(3000, band_width as u64));
+        }
+    }
+
+    #[test]
+    fn test_wide_band_blocks_differ() {
+        let band: Words<16> = OkvsKey(7u64.to_le_bytes()).hash_to_band(0, 1024);
+        assert_ne!(band.0[..8], band.0[8..]);
+    }
+
+    #[test]
+    fn test_seed_changes_hashes() {
+        let key = OkvsKey(7u64.to_le_bytes());
+        assert_ne!(
+            key.hash_to_band::<u128>(0, 128),
+            key.hash_to_band::<u128>(1, 128)
+        );
+        let okvs = RbOkvs::new(100).with_seed(42);
+        let encoding = okvs.encode(pairs(100, 0)).unwrap();
+        assert_eq!(encoding.seed(), 42);
+    }
+
+    #[test]
+    fn test_seed_determines_encoding() {
+        // fewer pairs than columns, so some columns are not determined by the pairs
+        let okvs = RbOkvs::new(1000);
+        let encoding = okvs.clone().with_seed(3).encode(pairs(900, 0)).unwrap();
+        assert_eq!(
+            okvs.clone().with_seed(3).encode(pairs(900, 0)).unwrap(),
+            encoding
+        );
+        assert_ne!(
+            okvs.clone().with_seed(4).encode(pairs(900, 0)).unwrap(),
+            encoding
+        );
+    }
+
+    #[test]
+    fn test_wire_roundtrip() {
+        for n in [10, 1000] {
+            let okvs = RbOkvs::new(n).with_seed(7);
+            let input = pairs(n, 0);
+            let encoding = okvs.encode(input.clone()).unwrap();
+            let decoded = Encoding::<OkvsValue>::from_bytes(&encoding.to_bytes()).unwrap();
+            assert_eq!(decoded, encoding);
+
+            // a process that only has the bytes decodes with the OKVS of the encoding
+            let decoded_okvs = decoded.okvs();
+            assert_eq!(decoded_okvs.columns(), okvs.columns());
+            assert_eq!(decoded_okvs.band_width(), okvs.band_width());
+            for (key, value) in &input {
+                assert_eq!(&decoded_okvs.decode(&decoded, key).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wire_rejects_invalid_input() {
+        use crate::okvs::wire::error::Error as WireError;
+
+        let okvs = RbOkvs::new(100);
+        let bytes = okvs.encode(pairs(100, 0)).unwrap().to_bytes();
+
+        // another value size
+        assert_eq!(
+            Encoding::<OkvsValue<16>>::from_bytes(&bytes).unwrap_err(),
+            WireError::Value(0)
+        );
+        assert!(matches!(
+            Encoding::<OkvsValue>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(WireError::Truncated { .. })
+        ));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(
+            Encoding::<OkvsValue>::from_bytes(&longer).unwrap_err(),
+            WireError::TrailingBytes(1)
+        );
+
+        // a band wider than the encoding (offset of the band width after the header)
+        let mut wide = bytes.clone();
+        wide[14..18].copy_from_slice(&(okvs.columns() as u32 + 8).to_le_bytes());
+        assert!(matches!(
+            Encoding::<OkvsValue>::from_bytes(&wide),
+            Err(WireError::Parameters(_))
+        ));
+        // more columns than bytes
+        let mut columns = bytes;
+        columns[6..14].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            Encoding::<OkvsValue>::from_bytes(&columns),
+            Err(WireError::Parameters(_) | WireError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_mismatched_encoding() {
+        let encoding = RbOkvs::new(1000).encode(pairs(1000, 0)).unwrap();
+        let key = OkvsKey(1u64.to_le_bytes());
+        for okvs in [RbOkvs::new(900), RbOkvs::new(10)] {
+            assert!(matches!(
+                okvs.decode(&encoding, &key),
+                Err(Error::Decode(_))
+            ));
+        }
+        let params = RbOkvsParams {
+            epsilon: 0.1,
+            band_width: 64,
+        };
+        assert!(matches!(
+            RbOkvs::with_params(1000, params).decode(&encoding, &key),
+            Err(Error::Decode(_))
+        ));
+    }
+
+    fn byte_pairs(n: usize, value_size: usize) -> Vec<(OkvsKey, Vec<u8>)> {
+        (0..n as u64)
+            .map(|i| {
+                let value = utils::hash(&i.to_le_bytes(), value_size);
+                (OkvsKey(i.to_le_bytes()), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        for (n, value_size) in [(10, 1), (1000, 100), (200, 1024)] {
+            let okvs = RbOkvs::new(n);
+            let input = byte_pairs(n, value_size);
+            let encoding = okvs
+                .encode_bytes(
+                    input.iter().map(|(k, v)| (k.clone(), &v[..])).collect(),
+                    value_size,
+                )
+                .unwrap();
+
+            // one buffer of all values
+            assert_eq!(encoding.values().value_size(), value_size);
+            assert_eq!(
+                encoding.values().as_bytes().len(),
+                okvs.columns() * value_size
+            );
+            for (key, value) in &input {
+                assert_eq!(&okvs.decode(&encoding, key).unwrap(), value);
+            }
+
+            let decoded = Encoding::<Bytes>::from_bytes(&encoding.to_bytes()).unwrap();
+            assert_eq!(decoded, encoding);
+        }
+    }
+
+    #[test]
+    fn test_bytes_value_size() {
+        let input = byte_pairs(10, 32);
+        let mut pairs: Vec<_> = input.iter().map(|(k, v)| (k.clone(), &v[..])).collect();
+        pairs[4].1 = &pairs[4].1[..31];
+        let okvs = RbOkvs::new(10);
+        assert!(matches!(
+            okvs.encode_bytes(pairs.clone(), 32),
+            Err(Error::ValueSize {
+                index: 4,
+                expected: 32,
+                found: 31
+            })
+        ));
+        assert!(matches!(
+            okvs.encode_bytes(pairs, 0),
+            Err(Error::Parameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_dense_layout() {
+        let okvs = RbOkvs::new(10);
+        assert_eq!(okvs.columns(), 56);
+        assert_eq!(okvs.band_width(), 56);
+
+        let okvs = RbOkvs::new(1000);
+        assert!(!okvs.is_dense());
+        assert_eq!(okvs.columns(), 1100);
+        assert_eq!(okvs.band_width(), 128);
+    }
+}
@@ -0,0 +1,202 @@
+//! Parameters of RB-OKVS derived from a statistical security parameter.
+//!
+//! An RB-OKVS for n pairs has m = (1 + ε) * n columns and every row has a random band
+//! of w bits. Encoding fails with probability about 2^-λ. For a fixed ε, the paper
+//! (Bienstock et al., Near-Optimal Oblivious Key-Value Stores for Efficient PSI, PSU
+//! and Volume-Hiding Multi-Maps) fits λ as a linear function of w. A small ε gives a
+//! high rate n / m (a small encoding), a large ε allows a narrow band (fast encoding
+//! and decoding).
+
+use super::error::{Error, Result};
+
+/// The largest band width the encoder supports.
+pub const MAX_BAND_WIDTH: usize = 1024;
+
+/// The fitted trade-offs as (ε, a, b) with λ = a * w + b, ordered by ε.
+const FITS: [(f64, f64, f64); 4] = [
+    (0.03, 0.08047, -3.464),
+    (0.05, 0.1314, -8.445),
+    (0.07, 0.1839, -12.17),
+    (0.1, 0.2691, -15.21),
+];
+
+/// The expansion ε and the band width w of an RB-OKVS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RbOkvsParams {
+    pub epsilon: f64,
+    pub band_width: usize,
+}
+
+impl Default for RbOkvsParams {
+    /// ε = 0.1 with a band of 128 bits, which gives λ ≈ 19.
+    fn default() -> Self {
+        Self {
+            epsilon: 0.1,
+            band_width: 128,
+        }
+    }
+}
+
+impl RbOkvsParams {
+    /// The fastest parameters, i.e., the largest fitted ε and its narrowest band for λ,
+    /// whose rate n / m is at least `rate`. Fails if no fitted ε gives the rate with a
+    /// band of at most [`MAX_BAND_WIDTH`] bits.
+    pub fn for_rate(lambda: usize, rate: f64) -> Result<Self> {
+        let params = FITS
+            .iter()
+            .rev()
+            .filter(|(epsilon, _, _)| 1.0 / (1.0 + epsilon) >= rate)
+            .find_map(|&fit| Self::from_fit(lambda, fit, MAX_BAND_WIDTH));
+
+        params.ok_or_else(|| {
+            Error::Parameters(format!(
+                "no band of at most {} bits gives λ = {} at rate {}",
+                MAX_BAND_WIDTH, lambda, rate
+            ))
+        })
+    }
+
+    /// The parameters with the highest rate whose band width is at most `band_width`,
+    /// which bounds the cost of encoding (O(n * w)) and decoding (O(w)).
+    /// Fails if even the largest fitted ε needs a wider band for λ.
+    pub fn for_speed(lambda: usize, band_width: usize) -> Result<Self> {
+        let max_band_width = band_width.min(MAX_BAND_WIDTH);
+        let params = FITS
+            .iter()
+            .find_map(|&fit| Self::from_fit(lambda, fit, max_band_width));
+
+        params.ok_or_else(|| {
+            Error::Parameters(format!(
+                "no band of at most {} bits gives λ = {}",
+                max_band_width, lambda
+            ))
+        })
+    }
+
+    /// The narrowest band for the fitted ε that gives λ, rounded up to whole bytes.
+    fn from_fit(lambda: usize, (epsilon, a, b): (f64, f64, f64), max: usize) -> Option<Self> {
+        let band_width = ((lambda as f64 - b) / a).ceil().max(8.0) as usize;
+        let band_width = band_width.next_multiple_of(8);
+        (band_width <= max).then_some(Self {
+            epsilon,
+            band_width,
+        })
+    }
+
+    /// The rate n / m, i.e., the number of pairs per column.
+    pub fn rate(&self) -> f64 {
+        1.0 / (1.0 + self.epsilon)
+    }
+
+    /// The statistical security λ of the parameters, estimated with the fit of the
+    /// largest fitted ε that is at most the ε of the parameters. Fails if ε is below
+    /// the smallest fitted ε, as no fit bounds the failure probability there.
+    pub fn security(&self) -> Result<f64> {
+        let (_, a, b) = FITS
+            .iter()
+            .rev()
+            .find(|(epsilon, _, _)| *epsilon <= self.epsilon)
+            .ok_or_else(|| {
+                Error::Parameters(format!(
+                    "no fit for ε = {} below the smallest fitted ε = {}",
+                    self.epsilon, FITS[0].0
+                ))
+            })?;
+        Ok(a * self.band_width as f64 + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::okvs::{Okvs, OkvsKey, OkvsValue, RbOkvs};
+    use super::*;
+
+    #[test]
+    fn test_for_speed() {
+        // λ = 40 needs 208 bits for ε = 0.1, 288 for ε = 0.07
+        let params = RbOkvsParams::for_speed(40, 256).unwrap();
+        assert_eq!(params.epsilon, 0.1);
+        assert_eq!(params.band_width, 208);
+        assert!(params.security().unwrap() >= 40.0);
+
+        // λ = 20 fits into 136 bits with ε = 0.1 and into 176 bits with ε = 0.07
+        let params = RbOkvsParams::for_speed(20, 192).unwrap();
+        assert_eq!(params.epsilon, 0.07);
+        assert_eq!(params.band_width, 176);
+        assert_eq!(RbOkvsParams::for_speed(20, 136).unwrap().epsilon, 0.1);
+
+        // λ = 80 needs 504 bits for ε = 0.07, wider than 256 bits
+        let params = RbOkvsParams::for_speed(80, 512).unwrap();
+        assert_eq!(params.epsilon, 0.07);
+        assert_eq!(params.band_width, 504);
+
+        assert!(matches!(
+            RbOkvsParams::for_speed(40, 128),
+            Err(Error::Parameters(_))
+        ));
+        assert!(matches!(
+            RbOkvsParams::for_speed(300, 2048),
+            Err(Error::Parameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_for_rate() {
+        let params = RbOkvsParams::for_rate(10, 0.95).unwrap();
+        assert_eq!(params.epsilon, 0.05);
+        assert!(params.rate() >= 0.95);
+        assert!(params.security().unwrap() >= 10.0);
+
+        // the fastest parameters for a low rate
+        assert_eq!(RbOkvsParams::for_rate(20, 0.5).unwrap().epsilon, 0.1);
+        // λ = 20 needs 296 bits for ε = 0.03
+        let params = RbOkvsParams::for_rate(20, 0.97).unwrap();
+        assert_eq!(params.epsilon, 0.03);
+        assert_eq!(params.band_width, 296);
+        // λ = 80 needs 1040 bits for ε = 0.03
+        assert!(RbOkvsParams::for_rate(80, 0.97).is_err());
+        assert!(RbOkvsParams::for_rate(10, 0.99).is_err());
+    }
+
+    #[test]
+    fn test_encode_with_params() {
+        let n = 5000;
+        let pairs: Vec<_> = (0..n as u64)
+            .map(|i| (OkvsKey(i.to_le_bytes()), OkvsValue((i * 7).to_le_bytes())))
+            .collect();
+
+        for params in [
+            RbOkvsParams::for_rate(10, 0.95).unwrap(),
+            RbOkvsParams::for_speed(40, 256).unwrap(),
+            RbOkvsParams::for_rate(40, 0.97).unwrap(),
+        ] {
+            let okvs = RbOkvs::with_params(n, params);
+            assert_eq!(okvs.band_width(), params.band_width);
+            let encoding = okvs.encode(pairs.clone()).unwrap();
+            assert_eq!(encoding.columns(), okvs.columns());
+            for (key, value) in &pairs {
+                assert_eq!(&okvs.decode(&encoding, key).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_default_security() {
+        let security = RbOkvsParams::default().security().unwrap();
+        assert!(security > 19.0 && security < 20.0);
+    }
+
+    #[test]
+    fn test_security_below_fits() {
+        let params = RbOkvsParams {
+            epsilon: 0.03,
+            band_width: 296,
+        };
+        assert!(params.security().unwrap() >= 20.0);
+        let params = RbOkvsParams {
+            epsilon: 0.01,
+            ..params
+        };
+        assert!(matches!(params.security(), Err(Error::Parameters(_))));
+    }
+}
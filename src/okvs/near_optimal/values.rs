@@ -0,0 +1,366 @@
+//! Storage of the values of an encoding and of the right-hand side while encoding.
+//!
+//! Values of an [`OkvsV`] type have a size fixed by the type and are stored in a
+//! `Vec`. [`Bytes`] are byte strings whose size is chosen at runtime, e.g., from the
+//! metric and dimension of a subprotocol message. All values of one encoding have the
+//! same size and are stored back to back in one buffer ([`ByteValues`]).
+
+use super::band::Band;
+use super::field::Scalar;
+use super::okvs::OkvsV;
+use crate::okvs::wire::error::{Error, Result};
+use rand::RngCore;
+use std::fmt::Debug;
+
+/// The type of the values of an encoding: an [`OkvsV`] or [`Bytes`].
+pub trait Value {
+    type Values: Values;
+}
+
+impl<V: OkvsV> Value for V {
+    type Values = Vec<V>;
+}
+
+/// Byte strings of a size chosen at runtime, the same for all values of an encoding.
+/// They are added with XOR, i.e., they are vectors over GF(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bytes;
+
+impl Value for Bytes {
+    type Values = ByteValues;
+}
+
+/// A sequence of values of the same size, indexed by row or column.
+pub trait Values: Debug + Clone + PartialEq {
+    type Scalar: Scalar;
+    /// A single value, as returned by decoding.
+    type Value;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The size of a value in bytes.
+    fn value_size(&self) -> usize;
+
+    /// The values at the given indices, in that order.
+    fn select(&self, indices: &[usize]) -> Self;
+
+    /// `count` random values of the size of these values, drawn from `rng`.
+    fn random_like(&self, count: usize, rng: &mut impl RngCore) -> Self;
+
+    fn set_zero(&mut self, index: usize);
+
+    /// Multiplies the value at `index` by `scalar`.
+    fn scale(&mut self, index: usize, scalar: &Self::Scalar);
+
+    /// Adds `scalar` times the value at `src` to the value at `dst`, which differs.
+    fn add_scaled(&mut self, dst: usize, src: usize, scalar: &Self::Scalar);
+
+    /// Adds `scalar` times the value at `src` of `other` to the value at `dst`.
+    fn add_scaled_from(&mut self, dst: usize, other: &Self, src: usize, scalar: &Self::Scalar);
+
+    /// The sum of the values at the set bits of the band, where bit 0 is the value at
+    /// `start`.
+    fn inner_product<B: Band>(&self, band: &B, start: usize) -> Self::Value;
+
+    /// The values back to back. Panics if they differ in size.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// `count` values of `value_size` bytes each. Fails with [`Error::Value`] if a
+    /// value is not valid.
+    fn from_bytes(bytes: &[u8], count: usize, value_size: usize) -> Result<Self>;
+}
+
+/// Mutable references to two different elements of a slice.
+fn pair_mut<T>(items: &mut [T], dst: usize, src: usize) -> (&mut T, &T) {
+    assert_ne!(dst, src, "cannot add a value to itself");
+    if dst < src {
+        let (low, high) = items.split_at_mut(src);
+        (&mut low[dst], &high[0])
+    } else {
+        let (low, high) = items.split_at_mut(dst);
+        (&mut high[0], &low[src])
+    }
+}
+
+impl<V: OkvsV> Values for Vec<V> {
+    type Scalar = V::Scalar;
+    type Value = V;
+
+    fn len(&self) -> usize {
+        <[V]>::len(self)
+    }
+
+    fn value_size(&self) -> usize {
+        self.first().map_or(0, |value| value.to_bytes().len())
+    }
+
+    fn select(&self, indices: &[usize]) -> Self {
+        indices.iter().map(|&i| self[i].clone()).collect()
+    }
+
+    fn random_like(&self, count: usize, rng: &mut impl RngCore) -> Self {
+        (0..count).map(|_| V::random(rng)).collect()
+    }
+
+    fn set_zero(&mut self, index: usize) {
+        self[index] = V::default();
+    }
+
+    fn scale(&mut self, index: usize, scalar: &V::Scalar) {
+        self[index] = self[index].scale(scalar);
+    }
+
+    fn add_scaled(&mut self, dst: usize, src: usize, scalar: &V::Scalar) {
+        let (dst, src) = pair_mut(self, dst, src);
+        dst.in_place_add_scaled(src, scalar);
+    }
+
+    fn add_scaled_from(&mut self, dst: usize, other: &Self, src: usize, scalar: &V::Scalar) {
+        self[dst].in_place_add_scaled(&other[src], scalar);
+    }
+
+    fn inner_product<B: Band>(&self, band: &B, start: usize) -> V {
+        band.inner_product(&self[start..])
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let size = self.value_size();
+        let mut bytes = Vec::with_capacity(self.len() * size);
+        for value in self {
+            let value = value.to_bytes();
+            assert_eq!(
+                value.len(),
+                size,
+                "all values of an encoding must have the same size"
+            );
+            bytes.extend(value);
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8], count: usize, value_size: usize) -> Result<Self> {
+        assert_eq!(bytes.len(), count * value_size);
+        bytes
+            .chunks_exact(value_size)
+            .enumerate()
+            .map(|(i, value)| V::from_bytes(value).ok_or(Error::Value(i)))
+            .collect()
+    }
+}
+
+/// Byte strings of `value_size` bytes each, stored back to back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteValues {
+    value_size: usize,
+    data: Vec<u8>,
+}
+
+impl ByteValues {
+    /// No values of `value_size` bytes each. Panics if `value_size` is zero.
+    pub fn new(value_size: usize) -> Self {
+        Self::with_capacity(value_size, 0)
+    }
+
+    pub fn with_capacity(value_size: usize, count: usize) -> Self {
+        assert!(value_size > 0, "values must not be empty");
+        Self {
+            value_size,
+            data: Vec::with_capacity(value_size * count),
+        }
+    }
+
+    /// Appends a value. Panics if it does not have the size of the values.
+    pub fn push(&mut self, value: &[u8]) {
+        assert_eq!(
+            value.len(),
+            self.value_size,
+            "all values must have the same size"
+        );
+        self.data.extend_from_slice(value);
+    }
+
+    pub fn get(&self, index: usize) -> &[u8] {
+        &self.data[self.range(index)]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.value_size)
+    }
+
+    /// All values back to back.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn range(&self, index: usize) -> std::ops::Range<usize> {
+        index * self.value_size..(index + 1) * self.value_size
+    }
+}
+
+/// XORs `src` into `dst`, which have the same length, 16 bytes at a time. The loop
+/// over whole words has no dependencies between iterations, so the compiler can
+/// vectorize it.
+pub(crate) fn xor(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    let mut dst_words = dst.chunks_exact_mut(16);
+    let mut src_words = src.chunks_exact(16);
+    for (d, s) in (&mut dst_words).zip(&mut src_words) {
+        let word =
+            u128::from_ne_bytes(d.try_into().unwrap()) ^ u128::from_ne_bytes(s.try_into().unwrap());
+        d.copy_from_slice(&word.to_ne_bytes());
+    }
+
+    let (dst, src) = (dst_words.into_remainder(), src_words.remainder());
+    if dst.len() >= 8 {
+        let (d, rest) = dst.split_at_mut(8);
+        let word = u64::from_ne_bytes((&*d).try_into().unwrap())
+            ^ u64::from_ne_bytes(src[..8].try_into().unwrap());
+        d.copy_from_slice(&word.to_ne_bytes());
+        for (d, s) in rest.iter_mut().zip(&src[8..]) {
+            *d ^= s;
+        }
+    } else {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d ^= s;
+        }
+    }
+}
+
+impl Values for ByteValues {
+    type Scalar = bool;
+    type Value = Vec<u8>;
+
+    fn len(&self) -> usize {
+        self.data.len() / self.value_size
+    }
+
+    fn value_size(&self) -> usize {
+        self.value_size
+    }
+
+    fn select(&self, indices: &[usize]) -> Self {
+        let mut values = Self::with_capacity(self.value_size, indices.len());
+        for &i in indices {
+            values.push(self.get(i));
+        }
+        values
+    }
+
+    fn random_like(&self, count: usize, rng: &mut impl RngCore) -> Self {
+        let mut data = vec![0u8; count * self.value_size];
+        rng.fill_bytes(&mut data);
+        Self {
+            value_size: self.value_size,
+            data,
+        }
+    }
+
+    fn set_zero(&mut self, index: usize) {
+        let range = self.range(index);
+        self.data[range].fill(0);
+    }
+
+    fn scale(&mut self, index: usize, scalar: &bool) {
+        if !scalar {
+            self.set_zero(index);
+        }
+    }
+
+    fn add_scaled(&mut self, dst: usize, src: usize, scalar: &bool) {
+        assert_ne!(dst, src, "cannot add a value to itself");
+        if *scalar {
+            let size = self.value_size;
+            let (low, high) = self.data.split_at_mut(size * dst.max(src));
+            let (dst, src) = if dst < src {
+                (&mut low[size * dst..size * (dst + 1)], &high[..size])
+            } else {
+                (&mut high[..size], &low[size * src..size * (src + 1)])
+            };
+            xor(dst, src);
+        }
+    }
+
+    fn add_scaled_from(&mut self, dst: usize, other: &Self, src: usize, scalar: &bool) {
+        if *scalar {
+            let range = self.range(dst);
+            xor(&mut self.data[range], other.get(src));
+        }
+    }
+
+    fn inner_product<B: Band>(&self, band: &B, start: usize) -> Vec<u8> {
+        let size = self.value_size;
+        let mut result = vec![0u8; size];
+        for i in band.ones() {
+            let i = start + i;
+            xor(&mut result, &self.data[i * size..(i + 1) * size]);
+        }
+        result
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    fn from_bytes(bytes: &[u8], count: usize, value_size: usize) -> Result<Self> {
+        assert_eq!(bytes.len(), count * value_size);
+        Ok(Self {
+            value_size,
+            data: bytes.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_values() {
+        let mut values = ByteValues::new(3);
+        values.push(&[1, 2, 3]);
+        values.push(&[4, 5, 6]);
+        values.push(&[7, 8, 9]);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values.as_bytes(), &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let mut selected = values.select(&[2, 0]);
+        assert_eq!(selected.iter().collect::<Vec<_>>(), [[7, 8, 9], [1, 2, 3]]);
+        selected.add_scaled(0, 1, &true);
+        assert_eq!(selected.get(0), [6, 10, 10]);
+        selected.add_scaled(1, 0, &false);
+        assert_eq!(selected.get(1), [1, 2, 3]);
+        selected.add_scaled_from(1, &values, 1, &true);
+        assert_eq!(selected.get(1), [5, 7, 5]);
+        selected.scale(0, &false);
+        assert_eq!(selected.get(0), [0, 0, 0]);
+
+        assert_eq!(values.inner_product(&0b101u64, 0), vec![6, 10, 10]);
+        // bit 0 is the value at the start
+        assert_eq!(values.inner_product(&0b11u64, 1), vec![3, 13, 15]);
+    }
+
+    #[test]
+    fn test_xor() {
+        // whole words, a u64 and single bytes
+        for len in [0, 1, 7, 8, 9, 16, 25, 31, 1024] {
+            let a: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let b: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            let mut c = a.clone();
+            xor(&mut c, &b);
+            assert!(c
+                .iter()
+                .zip(a.iter().zip(&b))
+                .all(|(c, (a, b))| *c == a ^ b));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "same size")]
+    fn test_byte_values_size() {
+        ByteValues::new(3).push(&[1, 2]);
+    }
+}
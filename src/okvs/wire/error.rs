@@ -0,0 +1,33 @@
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("Not an OKVS encoding")]
+    Magic,
+
+    #[error("Unsupported version {0}")]
+    Version(u8),
+
+    #[error("Unknown OKVS kind {0}")]
+    UnknownKind(u8),
+
+    #[error("Expected an encoding of kind {expected:?}, got {found:?}")]
+    Kind {
+        expected: super::Kind,
+        found: super::Kind,
+    },
+
+    #[error("Truncated encoding: needs {needed} more bytes, got {available}")]
+    Truncated { needed: usize, available: usize },
+
+    #[error("{0} trailing bytes after the encoding")]
+    TrailingBytes(usize),
+
+    #[error("Invalid parameters: {0}")]
+    Parameters(String),
+
+    #[error("Invalid value at index {0}")]
+    Value(usize),
+}
@@ -0,0 +1,206 @@
+//! Self-describing binary format of OKVS encodings, so that an encoding produced by one
+//! process can be decoded by another.
+//!
+//! An encoding starts with a header of the magic bytes `OKVS`, the format version and
+//! the kind of the OKVS, followed by the parameters of the kind and the payload. All
+//! integers are little endian. Readers validate the whole header and reject truncated
+//! input and trailing bytes.
+
+use error::{Error, Result};
+
+pub mod error;
+
+/// The magic bytes at the start of every encoding.
+pub const MAGIC: [u8; 4] = *b"OKVS";
+
+/// The current version of the format.
+pub const VERSION: u8 = 1;
+
+/// The OKVS that produced an encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// [`RbOkvs`](super::near_optimal::okvs::RbOkvs) with the number of columns (u64),
+    /// the band width (u32), the seed (u64) and the value size in bytes (u32), followed
+    /// by the values of all columns.
+    RbOkvs = 1,
+    /// [`LagrangePolynomialOKVS`](super::lagrange::LagrangePolynomialOKVS) with the
+    /// number of coefficients (u64) and the size of a field element in bytes (u32),
+    /// followed by the coefficients from the lowest degree.
+    Lagrange = 2,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = Error;
+
+    fn try_from(kind: u8) -> Result<Self> {
+        match kind {
+            1 => Ok(Self::RbOkvs),
+            2 => Ok(Self::Lagrange),
+            _ => Err(Error::UnknownKind(kind)),
+        }
+    }
+}
+
+/// The kind of a serialized encoding, after validating the magic bytes and the version.
+pub fn kind(bytes: &[u8]) -> Result<Kind> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(Error::Magic);
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+    Kind::try_from(reader.u8()?)
+}
+
+/// Writes the header of an encoding followed by its parameters and payload.
+pub(crate) struct Writer(Vec<u8>);
+
+impl Writer {
+    pub fn new(kind: Kind) -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, kind as u8]);
+        Self(bytes)
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Reads an encoding after its header.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Validates the header and expects an encoding of the given kind.
+    pub fn new(bytes: &'a [u8], expected: Kind) -> Result<Self> {
+        let found = kind(bytes)?;
+        if found != expected {
+            return Err(Error::Kind { expected, found });
+        }
+        Ok(Self {
+            bytes: &bytes[MAGIC.len() + 2..],
+        })
+    }
+
+    pub fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.bytes.len() {
+            return Err(Error::Truncated {
+                needed: n,
+                available: self.bytes.len(),
+            });
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A length from the encoding, which must fit into the address space.
+    pub fn len(&mut self) -> Result<usize> {
+        let len = self.u64()?;
+        usize::try_from(len).map_err(|_| Error::Parameters(format!("length {} is too large", len)))
+    }
+
+    /// The bytes of `count` items of `size` bytes each. Fails before allocating if the
+    /// input is shorter, so the sizes in a header do not need to be trusted.
+    pub fn items(&mut self, count: usize, size: usize) -> Result<&'a [u8]> {
+        let total = count.checked_mul(size).ok_or_else(|| {
+            Error::Parameters(format!("{} items of {} bytes are too large", count, size))
+        })?;
+        self.take(total)
+    }
+
+    /// Fails if bytes are left after the encoding.
+    pub fn finish(self) -> Result<()> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            n => Err(Error::TrailingBytes(n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let mut writer = Writer::new(Kind::Lagrange);
+        writer.u64(7);
+        let bytes = writer.finish();
+        assert_eq!(kind(&bytes), Ok(Kind::Lagrange));
+
+        let mut reader = Reader::new(&bytes, Kind::Lagrange).unwrap();
+        assert_eq!(reader.u64(), Ok(7));
+        assert_eq!(reader.finish(), Ok(()));
+
+        assert!(matches!(
+            Reader::new(&bytes, Kind::RbOkvs),
+            Err(Error::Kind {
+                expected: Kind::RbOkvs,
+                found: Kind::Lagrange
+            })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert_eq!(
+            kind(b"OKV"),
+            Err(Error::Truncated {
+                needed: 4,
+                available: 3
+            })
+        );
+        assert_eq!(kind(b"OKVX\x01\x01"), Err(Error::Magic));
+        assert_eq!(kind(b"OKVS\x02\x01"), Err(Error::Version(2)));
+        assert_eq!(kind(b"OKVS\x01\x03"), Err(Error::UnknownKind(3)));
+        assert!(matches!(kind(b"OKVS\x01"), Err(Error::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_reader_bounds() {
+        let bytes = [&Writer::new(Kind::RbOkvs).finish()[..], &[1, 2, 3]].concat();
+        let mut reader = Reader::new(&bytes, Kind::RbOkvs).unwrap();
+        assert!(matches!(
+            reader.u32(),
+            Err(Error::Truncated {
+                needed: 4,
+                available: 3
+            })
+        ));
+        assert!(matches!(
+            reader.items(usize::MAX, 2),
+            Err(Error::Parameters(_))
+        ));
+        assert_eq!(reader.take(1), Ok(&[1u8][..]));
+        assert_eq!(reader.finish(), Err(Error::TrailingBytes(2)));
+    }
+}
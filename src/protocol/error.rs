@@ -0,0 +1,21 @@
+use crate::hash::Party;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Bin {bin} of {party:?} holds {count} items, but the bin capacity is {capacity}")]
+    BinOverflow {
+        party: Party,
+        bin: String,
+        count: usize,
+        capacity: usize,
+    },
+
+    #[error("The OKVS of {party:?} needs more pairs than fit into a usize")]
+    OkvsLength { party: Party },
+
+    #[error("OKVS error: {0}")]
+    Okvs(#[from] crate::okvs::near_optimal::error::Error),
+}
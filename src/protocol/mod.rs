@@ -0,0 +1,534 @@
+//! Driver of the fuzzy PSI protocol. Both parties run in the same process and the
+//! proximity subprotocols are replaced by a non-secure placeholder that sends the
+//! actual points through the OKVS. The driver only talks to the binning scheme,
+//! so every metric that implements [`BinningScheme`] can be plugged in.
+
+use crate::hash::encoding::{Coordinate, Encoder};
+use crate::hash::index::BinIndex;
+use crate::hash::{BinKey, BinningScheme, Party, Point};
+use crate::okvs::near_optimal::okvs::{Okvs, OkvsKey, OkvsValue, Pair, RbOkvs};
+use error::{Error, Result};
+use rand::RngCore;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+pub mod error;
+
+/// Points that can be sent as the value of an OKVS.
+pub trait Message: Sized {
+    /// The number of bytes of the encoded point.
+    const SIZE: usize;
+
+    /// Encodes the point into a value of N bytes.
+    /// Panics if the point does not fit into N bytes.
+    fn to_value<const N: usize>(&self) -> OkvsValue<N>;
+
+    /// Decodes a point from a value of N bytes.
+    fn from_value<const N: usize>(value: &OkvsValue<N>) -> Self;
+}
+
+impl<const D: usize> Message for [u64; D] {
+    const SIZE: usize = 8 * D;
+
+    fn to_value<const N: usize>(&self) -> OkvsValue<N> {
+        assert!(
+            8 * D <= N,
+            "a point of dimension {} needs a value of at least {} bytes, got {}",
+            D,
+            8 * D,
+            N
+        );
+
+        let mut value = [0u8; N];
+        for (bytes, coordinate) in value.chunks_exact_mut(8).zip(self) {
+            bytes.copy_from_slice(&coordinate.to_le_bytes());
+        }
+        OkvsValue(value)
+    }
+
+    fn from_value<const N: usize>(value: &OkvsValue<N>) -> Self {
+        let mut point = [0u64; D];
+        for (coordinate, bytes) in point.iter_mut().zip(value.0.chunks_exact(8)) {
+            *coordinate = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        point
+    }
+}
+
+/// The number of zero bytes after the point in Bob's messages. Decoding a key that Bob
+/// did not write gives a random value, which only ends in a zero tag with probability
+/// 2^-64, so Alice can tell Bob's messages from unwritten slots.
+const TAG_SIZE: usize = 8;
+
+/// Whether the value ends in a zero tag.
+fn has_tag<const V: usize>(value: &OkvsValue<V>) -> bool {
+    value.0[V - TAG_SIZE..].iter().all(|&byte| byte == 0)
+}
+
+fn random_value<const V: usize>(rng: &mut impl RngCore) -> OkvsValue<V> {
+    let mut value = [0u8; V];
+    rng.fill_bytes(&mut value);
+    OkvsValue(value)
+}
+
+/// Fills the pairs up to the given length with random keys and values.
+fn pad<const K: usize, const V: usize>(
+    pairs: &mut Vec<Pair<OkvsKey<K>, OkvsValue<V>>>,
+    length: usize,
+    rng: &mut impl RngCore,
+) {
+    while pairs.len() < length {
+        let mut key = [0u8; K];
+        rng.fill_bytes(&mut key);
+        pairs.push((OkvsKey(key), random_value(rng)));
+    }
+}
+
+/// The number of pairs in the OKVS of a party, given the number of items, the largest
+/// number of bins per item and the bin capacity. Alice stores one pair per slot of each
+/// of her bins. Bob stores at most one pair per slot of Alice and item in each of his
+/// bins. Both are bounded by one pair per item, bin of the item and slot, so the OKVS
+/// is always padded to that size. Fails if the length does not fit into a usize.
+fn okvs_length<S: BinningScheme>(
+    scheme: &S,
+    party: Party,
+    items: usize,
+    capacity: usize,
+) -> Result<usize> {
+    scheme
+        .max_bins(party)
+        .and_then(|bins_per_item| bins_per_item.checked_mul(items))
+        .and_then(|pairs| pairs.checked_mul(capacity))
+        .ok_or(Error::OkvsLength { party })
+}
+
+/// Key of one subprotocol instance in an OKVS: the encoded bin followed by the slot of
+/// Alice's item and the slot of Bob's item in that bin.
+fn slot_key<B: BinKey, const K: usize>(bin: &B, slot_a: usize, slot_b: usize) -> OkvsKey<K> {
+    let mut key = bin.to_key::<K>();
+    key.0[K - 2] = slot_a as u8;
+    key.0[K - 1] = slot_b as u8;
+    key
+}
+
+/// Runs the fuzzy PSI protocol between Alice (`items_a`) and Bob (`items_b`) and
+/// returns the items of Bob that Alice found to be close to one of her items.
+/// Bins are encoded into OKVS keys of `K` bytes and points into values of `V` bytes.
+/// Bob's values end in a zero tag of 8 bytes, so `V` needs room for the point and the tag.
+///
+/// Each bin holds up to `capacity` items per party. For every bin, one subprotocol
+/// instance runs per pair of Alice's and Bob's slots, and empty slots are padded
+/// with random messages, so the messages do not reveal how full a bin is.
+/// If a bin holds more items than the capacity (i.e., the input violates the
+/// separation assumption), the protocol fails with [`Error::BinOverflow`] instead of
+/// dropping items. A capacity of 1 requires every bin to hold at most one item.
+pub fn run<S, const K: usize, const V: usize>(
+    scheme: &S,
+    capacity: usize,
+    items_a: &[S::Point],
+    items_b: &[S::Point],
+    rng: &mut impl RngCore,
+) -> Result<Vec<S::Point>>
+where
+    S: BinningScheme,
+    S::Point: Message,
+{
+    assert!(
+        (1..=256).contains(&capacity),
+        "the bin capacity must be between 1 and 256, got {}",
+        capacity
+    );
+    assert!(
+        S::Bin::SIZE + 2 <= K,
+        "a bin needs a key of at least {} bytes to hold the slots, got {}",
+        S::Bin::SIZE + 2,
+        K
+    );
+    assert!(
+        S::Point::SIZE + TAG_SIZE <= V,
+        "a point needs a value of at least {} bytes to hold the tag, got {}",
+        S::Point::SIZE + TAG_SIZE,
+        V
+    );
+
+    // First part: Alice creates the bins and stores them into the OKVS.
+    let okvs_a_length = okvs_length(scheme, Party::Alice, items_a.len(), capacity)?;
+    let index_a = scheme.index(Party::Alice, items_a);
+    check_capacity(&index_a, Party::Alice, capacity)?;
+    let okvs_a = RbOkvs::new(okvs_a_length).with_seed(rng.next_u64());
+    let mut s_a = Vec::new();
+    for (bin, inv) in index_a.iter() {
+        for slot_a in 0..capacity {
+            // execute subprot 1, or pad the slot with a random message
+            let m_1 = match inv.get(slot_a) {
+                Some(item) => item.to_value::<V>(),
+                None => random_value(rng),
+            };
+            s_a.push((slot_key::<_, K>(bin, slot_a, 0), m_1));
+        }
+    }
+    pad(&mut s_a, okvs_a_length, rng);
+    let enc_a = okvs_a.encode(s_a)?;
+
+    // Part two: Bob receives the encoding, creates his own bins and decodes the
+    // values from Alice. Then, he calculates his own message part for the bin.
+    let okvs_b_length = okvs_length(scheme, Party::Bob, items_b.len(), capacity)?;
+    let index_b = scheme.index(Party::Bob, items_b);
+    check_capacity(&index_b, Party::Bob, capacity)?;
+    let okvs_b = RbOkvs::new(okvs_b_length).with_seed(rng.next_u64());
+    let mut s_b = Vec::new();
+    for (bin, inv) in index_b.iter() {
+        for slot_a in 0..capacity {
+            let m_1 = okvs_a.decode(&enc_a, &slot_key::<_, K>(bin, slot_a, 0))?;
+            let val_from_a = S::Point::from_value(&m_1);
+
+            // execute prot 1 for each of Bob's items in the bin
+            for (slot_b, point) in inv.iter().enumerate() {
+                // "subprotocol 2": check if they are close, if yes, add the point with
+                // the bin to the okvs
+                if scheme.is_close(&val_from_a, point) {
+                    s_b.push((slot_key::<_, K>(bin, slot_a, slot_b), point.to_value::<V>()));
+                }
+            }
+        }
+    }
+    pad(&mut s_b, okvs_b_length, rng);
+    let enc_b = okvs_b.encode(s_b)?;
+
+    // Part three: Alice receives the encoding from Bob and decodes the values (with
+    // the bins) and then determines if the values are close enough.
+    let mut intersection = Vec::new();
+    let mut found = HashSet::new();
+    for (bin, inv) in index_a.iter() {
+        for (slot_a, state_val) in inv.iter().enumerate() {
+            for slot_b in 0..capacity {
+                let m_2 = okvs_b.decode(&enc_b, &slot_key::<_, K>(bin, slot_a, slot_b))?;
+
+                // execute subprot 3, skipping slots that Bob did not write
+                if !has_tag(&m_2) {
+                    continue;
+                }
+                let val_from_b = S::Point::from_value(&m_2);
+                if scheme.is_close(state_val, &val_from_b) && found.insert(val_from_b.clone()) {
+                    intersection.push(val_from_b);
+                }
+            }
+        }
+    }
+
+    Ok(intersection)
+}
+
+/// Runs the fuzzy PSI protocol on points in user units. Both parties' points are
+/// encoded onto the grid with the encoder, so the scheme must be built for the grid
+/// threshold given by [`Encoder::encode_delta`]. Returns the original items of Bob
+/// whose encodings are in the intersection. Points in the intersection that are not
+/// the encoding of any of Bob's items are skipped.
+pub fn run_encoded<S, C, const D: usize, const K: usize, const V: usize>(
+    scheme: &S,
+    encoder: &Encoder<C, D>,
+    capacity: usize,
+    items_a: &[[C; D]],
+    items_b: &[[C; D]],
+    rng: &mut impl RngCore,
+) -> Result<Vec<[C; D]>>
+where
+    S: BinningScheme<Point = Point<D>>,
+    C: Coordinate,
+{
+    let encoded_b = encoder.encode_all(items_b);
+    let mut originals: HashMap<Point<D>, Vec<[C; D]>> = HashMap::new();
+    for (encoded, item) in encoded_b.iter().zip(items_b) {
+        originals.entry(*encoded).or_default().push(*item);
+    }
+
+    let intersection = run::<_, K, V>(
+        scheme,
+        capacity,
+        &encoder.encode_all(items_a),
+        &encoded_b,
+        rng,
+    )?;
+    Ok(intersection
+        .iter()
+        .filter_map(|point| originals.get(point))
+        .flat_map(|items| items.iter().copied())
+        .collect())
+}
+
+/// Fails if any bin of the index holds more items than the capacity.
+fn check_capacity<B: Clone + Eq + Hash + Debug, P: Clone + Eq + Hash>(
+    index: &BinIndex<B, P>,
+    party: Party,
+    capacity: usize,
+) -> Result<()> {
+    match index.iter().find(|(_, items)| items.len() > capacity) {
+        Some((bin, items)) => Err(Error::BinOverflow {
+            party,
+            bin: format!("{:?}", bin),
+            count: items.len(),
+            capacity,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::angular::Angular;
+    use crate::hash::geo::Geo;
+    use crate::hash::grid::{Domain, Grid};
+    use crate::hash::hamming::Hamming;
+    use crate::hash::l1::L1;
+    use crate::hash::l2::{RotatedL2, L2};
+    use crate::hash::split::Split;
+    use crate::hash::LInfinity;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+        items.sort();
+        items.dedup();
+        items
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let point = [1u64, u64::MAX, 42u64];
+        let value = point.to_value::<24>();
+        assert_eq!(<[u64; 3]>::from_value(&value), point);
+    }
+
+    #[test]
+    fn test_run_exact_matches() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let items_a = vec![[1u64], [10u64], [100u64], [1000u64], [10000u64]];
+        let items_b = vec![[1u64], [1000u64], [100u64]];
+        let intersection =
+            run::<_, 16, 16>(&LInfinity::new(2), 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[1], [100], [1000]]);
+    }
+
+    #[test]
+    fn test_run_fuzzy_matches() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let items_a = vec![[10u64, 10u64], [100u64, 50u64], [500u64, 500u64]];
+        let items_b = vec![[12u64, 7u64], [103u64, 53u64], [300u64, 300u64]];
+
+        let intersection =
+            run::<_, 24, 24>(&LInfinity::new(3), 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7], [103, 53]]);
+
+        let intersection =
+            run::<_, 24, 24>(&Split::new(3, 1), 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7], [103, 53]]);
+
+        // l_2 distances are sqrt(13) and sqrt(18), l_1 distances are 5 and 6.
+        let intersection = run::<_, 24, 24>(&L2::new(4), 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7]]);
+
+        let intersection = run::<_, 24, 24>(&L1::new(5), 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7]]);
+    }
+
+    #[test]
+    fn test_run_random_grid() {
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let seed = [5u8; 32];
+        let items_a = vec![[10u64, 10u64], [100u64, 50u64], [500u64, 500u64]];
+        let items_b = vec![[12u64, 7u64], [103u64, 53u64], [300u64, 300u64]];
+
+        let scheme = LInfinity::with_grid(Grid::shifted(3, seed));
+        let intersection = run::<_, 24, 24>(&scheme, 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7], [103, 53]]);
+
+        let scheme = RotatedL2::new(4, seed);
+        let intersection = run::<_, 24, 24>(&scheme, 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[12, 7]]);
+    }
+
+    #[test]
+    fn test_run_encoded() {
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        let items_a = vec![[-1.25, 3.5], [10.0, -10.0]];
+        let items_b = vec![[-1.0, 3.25], [10.5, -10.5], [-20.0, 0.0]];
+
+        // distances are given in hundredths
+        let encoder = Encoder::new([-100.0, -100.0], 0.01);
+        let scheme = LInfinity::new(encoder.encode_delta(0.3));
+        let intersection =
+            run_encoded::<_, _, 2, 24, 24>(&scheme, &encoder, 1, &items_a, &items_b, &mut rng)
+                .unwrap();
+        assert_eq!(intersection, vec![[-1.0, 3.25]]);
+
+        let encoder = Encoder::new([i64::MIN, i64::MIN], 1);
+        let scheme = L1::new(encoder.encode_delta(3));
+        let intersection = run_encoded::<_, _, 2, 24, 24>(
+            &scheme,
+            &encoder,
+            1,
+            &[[-5i64, -5i64], [7, 7]],
+            &[[-6, -7], [7, 10], [30, 30]],
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(sorted(intersection), vec![[-6, -7], [7, 10]]);
+    }
+
+    #[test]
+    fn test_run_geo() {
+        let mut rng = ChaCha20Rng::seed_from_u64(6);
+        // Zurich main station, Sydney opera house
+        let items_a = vec![[47.37818, 8.54019], [-33.85678, 151.21530]];
+        // about 30 m and 90 m from the main station, about 20 m from the opera house
+        let items_b = vec![
+            [47.37845, 8.54017],
+            [47.37899, 8.54019],
+            [-33.85660, 151.21530],
+        ];
+
+        let scheme = Geo::new(50.0);
+        let intersection = run_encoded::<_, _, 2, 24, 24>(
+            &scheme,
+            &Geo::encoder(),
+            1,
+            &items_a,
+            &items_b,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(intersection.len(), 2);
+        assert!(intersection.contains(&[47.37845, 8.54017]));
+        assert!(intersection.contains(&[-33.85660, 151.21530]));
+    }
+
+    #[test]
+    fn test_run_angular() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let items_a = vec![[1.0, 0.0, 0.0], [0.0, 0.6, 0.8]];
+        // the first two are about 0.05 and 0.06 radians from Alice's vectors
+        let items_b = vec![[2.0, 0.1, 0.0], [0.0, 0.55, 0.85], [0.0, 0.0, -1.0]];
+
+        let scheme = Angular::<3>::new(0.1, 4, 1e-6, [3u8; 32]);
+        let intersection = run_encoded::<_, _, 3, 16, 32>(
+            &scheme,
+            &Angular::encoder(),
+            2,
+            &items_a,
+            &items_b,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(intersection.len(), 2);
+        assert!(intersection.contains(&[2.0, 0.1, 0.0]));
+        assert!(intersection.contains(&[0.0, 0.55, 0.85]));
+    }
+
+    #[test]
+    fn test_run_disjoint_inputs() {
+        // Unwritten slots of Bob's OKVS decode to random values, which must not be
+        // taken for points close to Alice's vectors.
+        let scheme = Angular::<3>::new(0.3, 4, 1e-6, [3u8; 32]);
+        let items_a = vec![[1.0, 0.0, 0.0], [0.0, 0.6, 0.8]];
+        for seed in 0..20 {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let intersection = run_encoded::<_, _, 3, 16, 32>(
+                &scheme,
+                &Angular::encoder(),
+                2,
+                &items_a,
+                &[[0.0, 0.0, -1.0]],
+                &mut rng,
+            )
+            .unwrap();
+            assert!(intersection.is_empty(), "{:?}", intersection);
+        }
+    }
+
+    #[test]
+    fn test_run_cyclic_domain() {
+        let mut rng = ChaCha20Rng::seed_from_u64(4);
+        // angles in degrees
+        let items_a = vec![[359u64], [90u64], [200u64]];
+        let items_b = vec![[1u64], [95u64], [180u64]];
+
+        let scheme = LInfinity::with_grid(Grid::new(2).with_domain(Domain::Cyclic(360)));
+        let intersection = run::<_, 16, 16>(&scheme, 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[1]]);
+    }
+
+    #[test]
+    fn test_run_hamming() {
+        let mut rng = ChaCha20Rng::seed_from_u64(6);
+        let template = 0x0123_4567_89ab_cdefu64;
+        let items_a = vec![[template, 0u64], [!template, u64::MAX]];
+        // Three and five flipped bits, and an unrelated string.
+        let items_b = vec![
+            [template ^ 0b1001, 1u64 << 40],
+            [!template ^ 0b11, u64::MAX ^ 0b111],
+            [0xdead_beef, 0xdead_beef],
+        ];
+        let scheme = Hamming::<2>::new(128, 4);
+
+        let intersection = run::<_, 20, 24>(&scheme, 1, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![items_b[0]]);
+    }
+
+    #[test]
+    fn test_run_multiple_items_per_bin() {
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        // With delta 5, 20 and 23 share the cell 2, as do 21 and 24.
+        let items_a = vec![[20u64], [23u64], [100u64]];
+        let items_b = vec![[21u64], [24u64], [100u64]];
+        let scheme = LInfinity::new(5);
+
+        let intersection = run::<_, 16, 16>(&scheme, 2, &items_a, &items_b, &mut rng).unwrap();
+        assert_eq!(sorted(intersection), vec![[21], [24], [100]]);
+    }
+
+    #[test]
+    fn test_run_bin_overflow() {
+        let mut rng = ChaCha20Rng::seed_from_u64(4);
+        let items_a = vec![[20u64], [100u64]];
+        let items_b = vec![[21u64], [24u64], [100u64]];
+        let scheme = LInfinity::new(5);
+
+        let result = run::<_, 16, 16>(&scheme, 1, &items_a, &items_b, &mut rng);
+        match result {
+            Err(Error::BinOverflow {
+                party,
+                count,
+                capacity,
+                ..
+            }) => {
+                assert_eq!(party, Party::Bob);
+                assert_eq!(count, 2);
+                assert_eq!(capacity, 1);
+            }
+            other => panic!("expected a bin overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_okvs_length_overflow() {
+        let mut rng = ChaCha20Rng::seed_from_u64(6);
+        // 2^64 bins per item for Alice
+        let scheme = LInfinity::<64>::new(2);
+        let result = run::<_, 520, 520>(&scheme, 1, &[[1u64; 64]], &[[1u64; 64]], &mut rng);
+        assert!(matches!(
+            result,
+            Err(Error::OkvsLength {
+                party: Party::Alice
+            })
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_run_key_without_room_for_slots() {
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        let _ = run::<_, 8, 16>(&LInfinity::new(2), 1, &[[1u64]], &[[1u64]], &mut rng);
+    }
+}